@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crokey::{KeyCombination, key};
+use crossterm::event::{Event, KeyEvent};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, List, ListState, Paragraph, Wrap},
+};
+use ratatui_macros::{horizontal, vertical};
+use serde::{Deserialize, Serialize};
+
+use crate::bbcode;
+use crate::widgets::{TextArea, TextAreaState};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Ad {
+    pub text: String,
+    /// Whether the rotation posts this ad.
+    pub rotate: bool,
+}
+
+/// A character's saved ads, and the channels the rotation posts them in.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AdLibrary {
+    pub ads: Vec<Ad>,
+    pub channels: Vec<String>,
+}
+
+/// Keeps track of when the server allows the next ad in every channel, and posts the rotation.
+pub struct AdPoster {
+    pub library: AdLibrary,
+    /// Longest ad the server accepts, in bytes. Sent as `lfrp_max`.
+    pub max_length: usize,
+    /// How long the server wants between ads in a channel. Sent as `lfrp_flood`.
+    pub flood: Duration,
+    /// How long the rotation waits between ads in a channel, if that's longer than `flood`.
+    interval: Duration,
+    posted: HashMap<String, Instant>,
+    pub rotating: bool,
+    next: usize,
+}
+
+impl AdPoster {
+    pub fn new(library: AdLibrary, interval: Duration) -> Self {
+        AdPoster {
+            library,
+            max_length: 50000,
+            flood: Duration::from_secs(600),
+            interval,
+            posted: HashMap::new(),
+            rotating: false,
+            next: 0,
+        }
+    }
+
+    /// Picks up the limits the server sends in VAR.
+    pub fn variable(&mut self, name: &str, value: &serde_json::Value) {
+        match name {
+            "lfrp_max" => {
+                if let Some(max) = value.as_u64() {
+                    self.max_length = max as usize;
+                }
+            }
+            "lfrp_flood" => {
+                if let Some(seconds) = value.as_f64() {
+                    self.flood = Duration::from_secs_f64(seconds);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// How long until the server accepts another ad in a channel.
+    pub fn wait(&self, channel: &str) -> Duration {
+        self.posted.get(channel).map_or(Duration::ZERO, |posted| {
+            self.flood.saturating_sub(posted.elapsed())
+        })
+    }
+
+    /// Whether the server would take this ad right now.
+    pub fn check(&self, channel: &str, text: &str) -> Result<(), String> {
+        if text.len() > self.max_length {
+            return Err(format!(
+                "Ads can't be longer than {} bytes, this one is {}.",
+                self.max_length,
+                text.len()
+            ));
+        }
+        let wait = self.wait(channel);
+        if !wait.is_zero() {
+            return Err(format!("The next ad can be posted in {}.", countdown(wait)));
+        }
+        Ok(())
+    }
+
+    pub fn posted(&mut self, channel: &str) {
+        self.posted.insert(channel.to_owned(), Instant::now());
+    }
+
+    /// The ads the rotation should post now, as channel and text, taking turns between the ads.
+    /// Channels that `allowed` says we can't post ads in right now are skipped.
+    pub fn tick(&mut self, allowed: impl Fn(&str) -> bool) -> Vec<(String, String)> {
+        if !self.rotating {
+            return Vec::new();
+        }
+        let ads: Vec<String> = self
+            .library
+            .ads
+            .iter()
+            .filter(|ad| ad.rotate && !ad.text.is_empty() && ad.text.len() <= self.max_length)
+            .map(|ad| ad.text.clone())
+            .collect();
+        if ads.is_empty() {
+            return Vec::new();
+        }
+        let interval = self.interval.max(self.flood);
+        let mut due = Vec::new();
+        for channel in &self.library.channels {
+            let waiting = self
+                .posted
+                .get(channel)
+                .is_some_and(|posted| posted.elapsed() < interval);
+            if waiting || !allowed(channel) {
+                continue;
+            }
+            due.push((channel.clone(), ads[self.next % ads.len()].clone()));
+            self.next += 1;
+        }
+        for (channel, _) in &due {
+            self.posted(channel);
+        }
+        due
+    }
+}
+
+/// Formats a wait as minutes and seconds.
+pub fn countdown(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub enum AdAction {
+    Close,
+    /// Post this ad once in the active channel.
+    Post(String),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Focus {
+    Ads,
+    Editor,
+    Channels,
+}
+
+/// Popup for writing ads, picking what the rotation posts and where, and posting an ad once.
+pub struct AdManager {
+    focus: Focus,
+    ads_state: ListState,
+    editor: TextAreaState,
+    /// Joined channels, and the rotation's channels we aren't in right now.
+    channels: Vec<String>,
+    channels_state: ListState,
+}
+
+impl AdManager {
+    pub fn new(library: &AdLibrary, mut channels: Vec<String>) -> Self {
+        for channel in &library.channels {
+            if !channels.contains(channel) {
+                channels.push(channel.clone());
+            }
+        }
+        let selected = (!library.ads.is_empty()).then_some(0);
+        AdManager {
+            focus: Focus::Ads,
+            ads_state: ListState::default().with_selected(selected),
+            editor: TextAreaState::with_text(
+                library
+                    .ads
+                    .first()
+                    .map(|ad| ad.text.clone())
+                    .unwrap_or_default(),
+            ),
+            channels_state: ListState::default().with_selected((!channels.is_empty()).then_some(0)),
+            channels,
+        }
+    }
+
+    /// Writes the editor back into the selected ad, creating one if there's text but no ad.
+    fn store(&mut self, library: &mut AdLibrary) {
+        let text = self.editor.text().trim().to_owned();
+        match self.ads_state.selected() {
+            Some(index) if index < library.ads.len() => library.ads[index].text = text,
+            _ if !text.is_empty() => {
+                library.ads.push(Ad {
+                    text,
+                    rotate: false,
+                });
+                self.ads_state.select(Some(library.ads.len() - 1));
+            }
+            _ => {}
+        }
+    }
+
+    fn load(&mut self, library: &AdLibrary) {
+        let text = self
+            .ads_state
+            .selected()
+            .and_then(|index| library.ads.get(index))
+            .map(|ad| ad.text.clone())
+            .unwrap_or_default();
+        self.editor = TextAreaState::with_text(text);
+    }
+
+    pub fn key(
+        &mut self,
+        key: KeyCombination,
+        event: KeyEvent,
+        poster: &mut AdPoster,
+    ) -> Option<AdAction> {
+        let library = &mut poster.library;
+        match key {
+            key!(esc) => {
+                self.store(library);
+                return Some(AdAction::Close);
+            }
+            key!(tab) | key!(shift - tab) => {
+                self.store(library);
+                self.focus = match (self.focus, key == key!(tab)) {
+                    (Focus::Ads, true) | (Focus::Channels, false) => Focus::Editor,
+                    (Focus::Editor, true) | (Focus::Ads, false) => Focus::Channels,
+                    (Focus::Channels, true) | (Focus::Editor, false) => Focus::Ads,
+                };
+            }
+            key!(ctrl - r) => poster.rotating = !poster.rotating,
+            key!(ctrl - n) => {
+                self.store(library);
+                library.ads.push(Ad::default());
+                self.ads_state.select(Some(library.ads.len() - 1));
+                self.load(library);
+                self.focus = Focus::Editor;
+            }
+            _ => match self.focus {
+                Focus::Ads => match key {
+                    key!(up) | key!(down) => {
+                        if key == key!(up) {
+                            self.ads_state.select_previous();
+                        } else {
+                            self.ads_state.select_next();
+                        }
+                        if let Some(index) = self.ads_state.selected() {
+                            self.ads_state
+                                .select(Some(index.min(library.ads.len().saturating_sub(1))));
+                        }
+                        self.load(library);
+                    }
+                    key!(enter) => {
+                        let index = self.ads_state.selected()?;
+                        return Some(AdAction::Post(library.ads.get(index)?.text.clone()));
+                    }
+                    key!(space) => {
+                        if let Some(ad) = self
+                            .ads_state
+                            .selected()
+                            .and_then(|index| library.ads.get_mut(index))
+                        {
+                            ad.rotate = !ad.rotate;
+                        }
+                    }
+                    key!(delete) => {
+                        if let Some(index) = self.ads_state.selected()
+                            && index < library.ads.len()
+                        {
+                            library.ads.remove(index);
+                            let selected = index.min(library.ads.len().saturating_sub(1));
+                            self.ads_state
+                                .select((!library.ads.is_empty()).then_some(selected));
+                            self.load(library);
+                        }
+                    }
+                    _ => {}
+                },
+                Focus::Editor => self.editor.event(&Event::Key(event)),
+                Focus::Channels => match key {
+                    key!(up) => self.channels_state.select_previous(),
+                    key!(down) => self.channels_state.select_next(),
+                    key!(space) => {
+                        let channel = self
+                            .channels_state
+                            .selected()
+                            .and_then(|index| self.channels.get(index))?;
+                        if let Some(index) = library.channels.iter().position(|c| c == channel) {
+                            library.channels.remove(index);
+                        } else {
+                            library.channels.push(channel.clone());
+                        }
+                    }
+                    _ => {}
+                },
+            },
+        }
+        None
+    }
+
+    pub fn paste(&mut self, data: &str) {
+        if self.focus == Focus::Editor {
+            self.editor.event(&Event::Paste(data.to_owned()));
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect, poster: &AdPoster) {
+        let block = Block::bordered().title(
+            "Ads (Tab: switch, Ctrl-N: new, Space: toggle, Enter: post, Del: delete, Ctrl-R: rotation, Esc: close)",
+        );
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        let [lists_area, status_area] = vertical![*=1, ==1].areas(inner);
+        let [ads_area, message_area, channels_area] =
+            horizontal![==30, *=1, ==24].areas(lists_area);
+        let [editor_area, preview_area] = vertical![*=1, *=1].areas(message_area);
+        let symbol = |focus| if self.focus == focus { "> " } else { "  " };
+        let ads = poster.library.ads.iter().map(|ad| {
+            let preview = bbcode::strip(&ad.text).replace('\n', " ");
+            let marker = if ad.rotate { "[x]" } else { "[ ]" };
+            format!("{} {}", marker, preview)
+        });
+        frame.render_stateful_widget(
+            List::new(ads)
+                .highlight_symbol(symbol(Focus::Ads))
+                .block(Block::bordered().title("Saved ads")),
+            ads_area,
+            &mut self.ads_state,
+        );
+        frame.render_stateful_widget_ref(TextArea::new(), editor_area, &mut self.editor);
+        frame.render_widget(
+            Paragraph::new(bbcode::render(self.editor.text()))
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title("Preview")),
+            preview_area,
+        );
+        let channels = self.channels.iter().map(|channel| {
+            let marker = if poster.library.channels.contains(channel) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            format!("{} {}", marker, channel)
+        });
+        frame.render_stateful_widget(
+            List::new(channels)
+                .highlight_symbol(symbol(Focus::Channels))
+                .block(Block::bordered().title("Rotate in")),
+            channels_area,
+            &mut self.channels_state,
+        );
+        let status = format!(
+            "Rotation {}, every {} per channel. Ads can be {} bytes long.",
+            if poster.rotating { "on" } else { "off" },
+            countdown(poster.interval.max(poster.flood)),
+            poster.max_length
+        );
+        frame.render_widget(status, status_area);
+    }
+}
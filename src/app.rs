@@ -1,49 +1,159 @@
-use crokey::key;
-use crossterm::event::{Event, KeyEvent};
-use fchat::{ClientMessage, ServerMessage, Ticket};
+use crokey::{KeyCombination, key};
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton,
+    MouseEvent, MouseEventKind,
+};
+use crossterm::execute;
+use fchat::{ChannelMode, ClientMessage, ServerMessage, Status, Ticket, TypingStatus};
 use miette::IntoDiagnostic;
 use ratatui::{
     DefaultTerminal,
-    text::Text,
-    widgets::{List, ListState, Paragraph},
+    layout::{Position, Rect},
+    style::Stylize,
+    widgets::{Clear, List, ListState},
 };
-use ratatui_macros::vertical;
-use ringbuffer::{AllocRingBuffer, RingBuffer};
+use ratatui_image::picker::Picker;
+use ratatui_image::{Resize, StatefulImage};
+use ratatui_macros::{horizontal, vertical};
+use std::collections::HashSet;
 use std::io;
+use std::time::Duration;
 use tokio::sync::mpsc::{Sender, UnboundedReceiver};
 use tui_prompts::{FocusState, Prompt, State, TextPrompt, TextRenderStyle, TextState};
 
+use crate::ads::{self, AdAction, AdLibrary, AdManager, AdPoster};
+use crate::bbcode::{self, Link};
+use crate::chat::{ChatLine, Conversations, LineKind, Target};
+use crate::clipboard;
+use crate::command::Command;
+use crate::config::Config;
+use crate::confirm::{Answer, Confirm, Confirmed};
+use crate::console::{self, ConsoleAction, DevConsole, Direction};
+use crate::context::{ContextAction, ContextChoice, ContextMenu};
+use crate::description::{DescriptionAction, DescriptionEditor};
+use crate::dice;
+use crate::fetch::Fetched;
+use crate::idle::IdleTracker;
+use crate::images::{ImageKey, Images};
+use crate::invites::{Invite, InviteAction, InviteList};
 use crate::io::ChatController;
-use crate::widgets::{TextArea, TextAreaState};
+use crate::layout::{self, ChatAreas, Layout, Split};
+use crate::links::{self, LinkPicker, PickerAction};
+use crate::logs::{self, ChatLog};
+use crate::mention::MentionMatcher;
+use crate::moderation::{MenuAction, ModAction, ModerationMenu};
+use crate::notify::{Notifier, Trigger};
+use crate::outgoing::Outgoing;
+use crate::preview::{Blocklist, PreviewPopup, Previews};
+use crate::report::{QueueAction, Report, ReportAction, ReportDialog, ReportQueue, StaffReport};
+use crate::rooms::{self, NewRoom, RoomAction, RoomDialog};
+use crate::search::{SearchAction, SearchQuery, SearchResult, SearchScreen};
+use crate::staff::StaffAction;
+use crate::status::{Availability, OwnStatus, StatusAction, StatusEditor};
+use crate::storage;
+use crate::typing::TypingTracker;
+use crate::widgets::{Description, MemberList, Scrollback, TabBar, TextArea, TextAreaState};
 
 pub type EventStream = UnboundedReceiver<AppEvent>;
 
+/// Columns of the avatar next to private conversations, which is half as many rows high.
+const AVATAR_WIDTH: u16 = 14;
+
+/// Rows scrolled by one turn of the mouse wheel.
+const WHEEL_ROWS: usize = 3;
+
 pub struct App {
     state: AppScreen,
+    popup: Option<Popup>,
     needs_redraw: bool,
     pub should_quit: bool,
     chat_controller: ChatController,
+    config: Config,
     character: String,
-    debug_data: AllocRingBuffer<String>,
-    sender: Option<Sender<ClientMessage>>,
-    last_event: Option<Event>,
+    mentions: MentionMatcher,
+    notifier: Notifier,
+    friends: HashSet<String>,
+    /// Characters on our ignore list, lowercased.
+    ignored: HashSet<String>,
+    /// Global chat operators, lowercased.
+    global_ops: HashSet<String>,
+    images: Images,
+    previews: Previews,
+    /// Whether the terminal gets links as OSC 8 hyperlinks.
+    hyperlinks: bool,
+    /// Whether mouse events are captured, instead of left to the terminal for selecting text.
+    mouse: bool,
+    layout: Layout,
+    /// Where the chat screen's panes were last drawn, to know what the mouse is over.
+    areas: ChatAreas,
+    /// Reports sent to the staff while we're connected, if we're a global operator.
+    reports: Vec<StaffReport>,
+    /// Room invites that weren't answered yet, oldest first.
+    invites: Vec<Invite>,
+    typing: TypingTracker,
+    status: OwnStatus,
+    idle: IdleTracker,
+    ads: AdPoster,
+    /// Whether the composer posts ads instead of messages in channels.
+    ad_mode: bool,
+    console: DevConsole,
+    sender: Option<Sender<Outgoing>>,
+    /// The room we asked the server to create, until we've joined it.
+    new_room: Option<NewRoom>,
+    /// Id of the last message queued to be shown as pending.
+    last_id: u64,
 }
 
 impl App {
-    pub fn new(chat_controller: ChatController) -> Self {
+    pub fn new(chat_controller: ChatController, config: Config, picker: Option<Picker>) -> Self {
         App {
             state: AppScreen::Login {
                 focus: 0,
                 username: TextState::new().with_focus(FocusState::Focused),
                 password: TextState::new().with_focus(FocusState::Unfocused),
             },
+            popup: None,
             needs_redraw: true,
             should_quit: false,
             chat_controller,
+            mentions: MentionMatcher::new("", &config.aliases),
+            notifier: Notifier::new(
+                config.notify_on.clone(),
+                config.notify_with.clone(),
+                config.notify_command.clone(),
+            ),
+            friends: HashSet::new(),
+            ignored: HashSet::new(),
+            global_ops: HashSet::new(),
+            images: Images::new(picker, config.data_dir.join("cache")),
+            hyperlinks: !config.no_hyperlinks
+                && supports_hyperlinks::on(supports_hyperlinks::Stream::Stdout),
+            mouse: config.mouse,
+            layout: storage::load(&config.data_dir.join("layout.json")),
+            areas: ChatAreas::default(),
+            previews: Previews::new(
+                config.data_dir.join("cache").join("previews"),
+                config.preview_max_size,
+                Blocklist::new(&config.preview_blocklist),
+                config.auto_preview,
+            ),
+            reports: Vec::new(),
+            invites: Vec::new(),
+            typing: TypingTracker::new(),
+            status: OwnStatus::default(),
+            idle: IdleTracker::new(
+                config.auto_away.map(minutes),
+                config.away_when_unfocused.map(minutes),
+                config.away_message.clone(),
+            ),
+            ads: AdPoster::new(AdLibrary::default(), minutes(config.ad_interval)),
+            ad_mode: false,
+            console: DevConsole::new(config.console_size),
+            config,
             character: String::new(),
-            debug_data: AllocRingBuffer::new(16),
             sender: None,
-            last_event: None,
+            new_room: None,
+            last_id: 0,
         }
     }
 
@@ -52,18 +162,7 @@ impl App {
             self.needs_redraw = false;
             terminal
                 .draw(|frame| {
-                    let [debug_area, main_area, status_area] =
-                        vertical![*=2, *=1, ==1].areas(frame.area());
-                    frame.render_widget(
-                        Paragraph::new(
-                            self.debug_data
-                                .iter()
-                                .map(|debug_string| &debug_string[..])
-                                .collect::<Text>(),
-                        ),
-                        debug_area,
-                    );
-                    frame.render_widget(format!("{:?}", self.last_event), status_area);
+                    let main_area = frame.area();
                     match &mut self.state {
                         AppScreen::Login {
                             username, password, ..
@@ -82,35 +181,207 @@ impl App {
                                 list_state,
                             );
                         }
-                        AppScreen::Chat { text_state, .. } => {
+                        AppScreen::Chat {
+                            text_state,
+                            conversations,
+                            ..
+                        } => {
+                            let description = Description::new(conversations.active());
+                            let channel =
+                                matches!(conversations.active().target, Target::Channel(_));
+                            let areas = self.layout.chat(main_area, &description, channel);
+                            self.areas = areas;
+                            frame.render_widget(TabBar::new(conversations), areas.tabs);
+                            let header = match &conversations.active().target {
+                                Target::Channel(channel) if self.ad_mode => {
+                                    let wait = self.ads.wait(channel);
+                                    format!(
+                                        "Ad mode (/ad to leave): {} / {} bytes, {}",
+                                        text_state.text().len(),
+                                        self.ads.max_length,
+                                        if wait.is_zero() {
+                                            String::from("ready to post")
+                                        } else {
+                                            format!("next ad in {}", ads::countdown(wait))
+                                        }
+                                    )
+                                }
+                                _ => conversations.active().header(),
+                            };
+                            frame.render_widget(header.italic(), areas.header);
+                            frame.render_widget(description, areas.description);
+                            if !areas.members.is_empty() {
+                                let members =
+                                    MemberList::new(conversations.active(), self.layout.members)
+                                        .avatars(self.images.shown);
+                                let avatars = members.avatar_areas(areas.members);
+                                frame.render_widget(members, areas.members);
+                                for (area, member) in avatars {
+                                    if let Some(protocol) =
+                                        self.images.get(&ImageKey::avatar(member))
+                                    {
+                                        frame.render_stateful_widget(
+                                            StatefulImage::new(None).resize(Resize::Fit(None)),
+                                            area,
+                                            protocol,
+                                        );
+                                    }
+                                }
+                            }
+                            // Private conversations show who they're with next to the scrollback
+                            let avatar = match &conversations.active().target {
+                                Target::Private(character)
+                                    if self.images.shown
+                                        && areas.scrollback.width
+                                            >= AVATAR_WIDTH + layout::MIN_SCROLLBACK_WIDTH =>
+                                {
+                                    Some(ImageKey::avatar(character))
+                                }
+                                _ => None,
+                            };
+                            let [scrollback_area, avatar_area] = if avatar.is_some() {
+                                horizontal![*=1, ==AVATAR_WIDTH].areas(areas.scrollback)
+                            } else {
+                                [areas.scrollback, Rect::default()]
+                            };
+                            self.areas.scrollback = scrollback_area;
+                            frame.render_stateful_widget_ref(
+                                Scrollback::new()
+                                    .hyperlinks(self.hyperlinks)
+                                    .eicons(self.images.shown),
+                                scrollback_area,
+                                conversations.active_mut(),
+                            );
+                            if let Some(avatar) = avatar
+                                && let Some(protocol) = self.images.get(&avatar)
+                            {
+                                let [area, _] =
+                                    vertical![==AVATAR_WIDTH / 2, *=1].areas(avatar_area);
+                                frame.render_stateful_widget(
+                                    StatefulImage::new(None).resize(Resize::Fit(None)),
+                                    area,
+                                    protocol,
+                                );
+                            }
+                            for (area, name) in &conversations.active().viewport.eicons {
+                                if let Some(protocol) = self.images.get(&ImageKey::eicon(name)) {
+                                    frame.render_widget(Clear, *area);
+                                    frame.render_stateful_widget(
+                                        StatefulImage::new(None).resize(Resize::Fit(None)),
+                                        *area,
+                                        protocol,
+                                    );
+                                } else {
+                                    // Until it's downloaded, or if it never will be
+                                    frame.render_widget(name.as_str().dark_gray(), *area);
+                                }
+                            }
                             frame.render_stateful_widget_ref(
                                 TextArea::new(),
-                                main_area,
+                                areas.composer,
                                 text_state,
                             );
                         }
                     };
+                    match &mut self.popup {
+                        Some(Popup::Status(editor)) => {
+                            let [_, area, _] = vertical![*=1, ==12, *=1].areas(main_area);
+                            editor.draw(frame, area);
+                        }
+                        Some(Popup::Search(screen)) => screen.draw(frame, main_area),
+                        Some(Popup::Ads(manager)) => manager.draw(frame, main_area, &self.ads),
+                        Some(Popup::Moderation(menu)) => {
+                            let [_, area, _] = vertical![*=1, ==16, *=1].areas(main_area);
+                            menu.draw(frame, area);
+                        }
+                        Some(Popup::Description(editor)) => editor.draw(frame, main_area),
+                        Some(Popup::Room(dialog)) => {
+                            let [_, area, _] = vertical![*=1, ==14, *=1].areas(main_area);
+                            dialog.draw(frame, area);
+                        }
+                        Some(Popup::Report(dialog)) => dialog.draw(frame, main_area),
+                        Some(Popup::Reports(queue)) => queue.draw(frame, main_area, &self.reports),
+                        Some(Popup::Invites(list)) => {
+                            let [_, area, _] = vertical![*=1, ==10, *=1].areas(main_area);
+                            list.draw(frame, area, &self.invites);
+                        }
+                        Some(Popup::Preview(preview)) => preview.draw(frame, main_area),
+                        Some(Popup::Links(picker)) => {
+                            let [_, area, _] = vertical![*=1, ==12, *=1].areas(main_area);
+                            picker.draw(frame, area);
+                        }
+                        Some(Popup::Confirm(confirm)) => {
+                            let [_, area, _] = vertical![*=1, ==4, *=1].areas(main_area);
+                            confirm.draw(frame, area);
+                        }
+                        Some(Popup::Context(menu)) => {
+                            let area = menu.draw(frame, main_area, self.images.shown);
+                            if !area.is_empty()
+                                && let Some(protocol) =
+                                    self.images.get(&ImageKey::avatar(&menu.character))
+                            {
+                                frame.render_stateful_widget(
+                                    StatefulImage::new(None).resize(Resize::Fit(None)),
+                                    area,
+                                    protocol,
+                                );
+                            }
+                        }
+                        Some(Popup::Console) => self.console.draw(frame, main_area),
+                        None => {}
+                    }
                 })
                 .into_diagnostic()?;
+            for request in self.images.take_missing() {
+                if !self.chat_controller.fetch(request.clone()) {
+                    self.images.retry(&request.url);
+                }
+            }
         }
         Ok(())
     }
 
     pub fn event(&mut self, event: AppEvent) -> miette::Result<()> {
+        // Ticks are too frequent to be worth a redraw and a debug line each
+        if let AppEvent::Tick = event {
+            self.tick();
+            return Ok(());
+        }
+        // Capturing the mouse reports every move, which changes nothing
+        if let AppEvent::Crossterm(Ok(Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Moved,
+            ..
+        }))) = event
+        {
+            return Ok(());
+        }
         self.needs_redraw = true;
-        self.debug_data.push(format!("{:?}", event));
+        match &event {
+            // Keys can be passwords, and a ticket is as good as one
+            AppEvent::Crossterm(_) | AppEvent::Ticket(_) | AppEvent::Outbound(_) => {}
+            AppEvent::Chat(message) => self
+                .console
+                .push(Direction::In, console::wire_text(message)),
+            event => self.console.push(Direction::App, format!("{:?}", event)),
+        }
         match event {
             AppEvent::Crossterm(event) => {
                 let event = event.unwrap();
-                self.last_event = Some(event.clone());
                 match event {
                     Event::Key(event) => self.key(event),
                     Event::Paste(data) => self.paste(data),
+                    Event::Mouse(event) => self.mouse_event(event),
+                    Event::FocusGained => {
+                        if let Some(status) = self.idle.focus_gained() {
+                            self.set_status(status, false);
+                        }
+                    }
+                    Event::FocusLost => self.idle.focus_lost(),
                     _ => {}
                 }
             }
-            AppEvent::Debug(debug_msg) => self.debug_data.push(debug_msg),
-            AppEvent::Chat(_) => {}
+            AppEvent::Outbound(frame) => self.console.push(Direction::Out, frame),
+            AppEvent::Chat(message) => self.chat(message),
             // TODO: Handle ticket errors
             AppEvent::Ticket(ticket) => {
                 // TODO: Handle ticket errors
@@ -144,19 +415,98 @@ impl App {
                     AppScreen::Characters { ticket, .. } => AppScreen::Chat {
                         ticket: ticket.clone(),
                         text_state: TextAreaState::new(),
+                        conversations: Conversations::new(self.chat_log(), self.config.history),
                     },
-                    // TODO: What to do in this case?
-                    AppScreen::Chat { .. } => return Ok(()),
+                    // Reconnected, so we're back in the channels we had open
+                    AppScreen::Chat { .. } => {
+                        self.sender = Some(sender);
+                        self.rejoin();
+                        self.connected();
+                        return Ok(());
+                    }
                 };
                 self.sender = Some(sender);
+                self.connected();
+            }
+            AppEvent::Disconnected(error) => self.connection_lost(error),
+            AppEvent::Sent(id) => {
+                if let AppScreen::Chat { conversations, .. } = &mut self.state {
+                    conversations.sent(id);
+                }
+            }
+            AppEvent::Cancelled(id) => {
+                if let AppScreen::Chat {
+                    conversations,
+                    text_state,
+                    ..
+                } = &mut self.state
+                    && let Some(text) = conversations.cancelled(id)
+                    && text_state.text().is_empty()
+                {
+                    *text_state = TextAreaState::with_text(text);
+                }
+            }
+            AppEvent::Fetched { url, result } => {
+                let ours = self.images.fetched(
+                    &url,
+                    result
+                        .as_ref()
+                        .map(|fetched| fetched.path.as_path())
+                        .map_err(Clone::clone),
+                );
+                // Downloads started ahead of time just wait in the cache
+                if !ours
+                    && let Some(Popup::Preview(preview)) = &mut self.popup
+                    && preview.url == url
+                {
+                    if let Ok(Fetched {
+                        content_type: Some(content_type),
+                        ..
+                    }) = &result
+                    {
+                        preview.content_type = Some(content_type.clone());
+                    }
+                    preview.loaded(result.and_then(|fetched| self.images.decode(&fetched.path)));
+                }
+            }
+            AppEvent::ReportUploaded { report, result } => {
+                let text = match &result {
+                    Ok(_) => format!("Report sent, with the last {} lines.", report.log.len()),
+                    Err(error) => {
+                        let copy = match report.save_log(&self.config.data_dir, &self.character) {
+                            Ok(path) => format!("A copy of the log is in {}.", path.display()),
+                            Err(error) => format!("The log couldn't be saved either: {}", error),
+                        };
+                        format!(
+                            "Report sent WITHOUT a log attached, because uploading it failed: {}. \
+                             Staff won't see the conversation. {}",
+                            error, copy
+                        )
+                    }
+                };
+                self.send(report.message(&self.character, result.ok()));
+                self.system_message(text);
+            }
+            AppEvent::Tick => unreachable!(),
+            AppEvent::Error(AppError::Connection(error)) => {
+                self.connection_lost(format!("{:?}", error));
             }
-            AppEvent::Error(_) => todo!(),
         }
         Ok(())
     }
 
     pub fn key(&mut self, event: KeyEvent) {
         let key = event.into();
+        if let Some(status) = self.idle.input() {
+            self.set_status(status, false);
+        }
+        if key != key!(ctrl - q) && self.popup.is_some() {
+            self.popup_key(key, event);
+            return;
+        }
+        if self.scrollback_search_key(key, event) {
+            return;
+        }
         match key {
             key!(ctrl - q) => {
                 self.should_quit = true;
@@ -177,10 +527,83 @@ impl App {
                     };
                     let character = ticket.characters[selected].clone();
                     let ticket = ticket.clone();
+                    self.mentions = MentionMatcher::new(&character, &self.config.aliases);
+                    self.character = character.clone();
                     let _ = self.chat_controller.connect(ticket, character);
                 }
-                AppScreen::Chat { text_state, .. } => text_state.event(&Event::Key(event)),
+                AppScreen::Chat { .. } => self.submit(),
             },
+            key!(ctrl - s) => {
+                if let AppScreen::Chat { conversations, .. } = &mut self.state {
+                    conversations.active_mut().start_search();
+                }
+            }
+            key!(pageup) | key!(pagedown) | key!(home) | key!(end)
+                if matches!(self.state, AppScreen::Chat { .. }) =>
+            {
+                if let AppScreen::Chat { conversations, .. } = &mut self.state {
+                    let conversation = conversations.active_mut();
+                    match key {
+                        key!(pageup) => conversation.page_up(),
+                        key!(pagedown) => conversation.page_down(),
+                        key!(home) => conversation.scroll_to_top(),
+                        _ => conversation.scroll_to_bottom(),
+                    }
+                }
+            }
+            key!(alt - s) => self.open_status_editor(),
+            key!(alt - a) => self.open_ad_manager(),
+            key!(ctrl - f) => self.open_search(""),
+            key!(alt - f) => {
+                if let AppScreen::Chat { conversations, .. } = &mut self.state {
+                    let conversation = conversations.active_mut();
+                    conversation.filter = conversation.filter.next();
+                }
+            }
+            key!(alt - c) => self.cancel_pending(),
+            key!(alt - o) => self.open_moderation_menu(),
+            key!(alt - d) => {
+                if let AppScreen::Chat { conversations, .. } = &mut self.state {
+                    let conversation = conversations.active_mut();
+                    conversation.description_expanded = !conversation.description_expanded;
+                }
+            }
+            key!(alt - e) => self.open_description_editor(),
+            key!(alt - l) => {
+                if let AppScreen::Chat { conversations, .. } = &self.state {
+                    let conversation = conversations.active();
+                    let lines = conversation
+                        .visible_lines()
+                        .iter()
+                        .filter(|line| conversation.filter.shows(line.kind));
+                    self.popup = Some(Popup::Links(LinkPicker::new(lines)));
+                }
+            }
+            key!(alt - left) => self.resize(Split::Left),
+            key!(alt - right) => self.resize(Split::Right),
+            key!(alt - up) => self.resize(Split::Up),
+            key!(alt - down) => self.resize(Split::Down),
+            key!(f12) => self.popup = Some(Popup::Console),
+            key!(alt - i) => {
+                let text = if self.images.toggle() {
+                    "Showing avatars and eicons."
+                } else if self.config.no_images {
+                    "Images are turned off with --no-images."
+                } else {
+                    "Showing avatars and eicons as text."
+                };
+                self.system_message(String::from(text));
+            }
+            key!(alt - m) => {
+                if let AppScreen::Chat { conversations, .. } = &mut self.state {
+                    conversations.active_mut().jump_to_last_mention();
+                }
+            }
+            key!(esc) => {
+                if let AppScreen::Chat { conversations, .. } = &mut self.state {
+                    conversations.active_mut().scroll_to_bottom();
+                }
+            }
             _ => match &mut self.state {
                 AppScreen::Login {
                     focus,
@@ -205,9 +628,32 @@ impl App {
                 AppScreen::Chat { text_state, .. } => text_state.event(&Event::Key(event)),
             },
         }
+        self.update_typing();
     }
 
     pub fn paste(&mut self, data: String) {
+        if let Some(status) = self.idle.input() {
+            self.set_status(status, false);
+        }
+        match &mut self.popup {
+            Some(Popup::Status(editor)) => return editor.paste(&data),
+            Some(Popup::Search(screen)) => return screen.paste(&data),
+            Some(Popup::Ads(manager)) => return manager.paste(&data),
+            Some(Popup::Room(dialog)) => return dialog.paste(&data),
+            Some(Popup::Description(editor)) => return editor.paste(&data),
+            Some(Popup::Report(dialog)) => return dialog.paste(&data),
+            Some(Popup::Console) => return self.console.paste(&data),
+            Some(
+                Popup::Moderation(_)
+                | Popup::Confirm(_)
+                | Popup::Reports(_)
+                | Popup::Invites(_)
+                | Popup::Preview(_)
+                | Popup::Links(_)
+                | Popup::Context(_),
+            ) => return,
+            None => {}
+        }
         match &mut self.state {
             AppScreen::Login {
                 focus,
@@ -221,7 +667,697 @@ impl App {
                 }
             }
             AppScreen::Characters { .. } => {}
-            AppScreen::Chat { .. } => todo!(),
+            AppScreen::Chat { text_state, .. } => text_state.event(&Event::Paste(data)),
+        }
+        self.update_typing();
+    }
+
+    fn tick(&mut self) {
+        if let Some((character, status)) = self.typing.tick() {
+            self.send(ClientMessage::TPN { character, status });
+        }
+        // Nobody sees our status before we're connected, so there's nothing to go away from
+        if self.sender.is_some()
+            && let Some(status) = self.idle.tick(&self.status)
+        {
+            self.set_status(status, false);
+        }
+        let AppScreen::Chat { conversations, .. } = &mut self.state else {
+            return;
+        };
+        let allowed = |channel: &str| {
+            conversations.tabs().iter().any(|tab| {
+                tab.target == Target::Channel(channel.to_owned())
+                    && tab.mode != ChannelMode::ChatOnly
+            })
+        };
+        let due = self.ads.tick(allowed);
+        for (channel, message) in due {
+            let line = ChatLine::new(LineKind::Ad, self.character.clone(), message.clone());
+            self.send_shown(
+                Target::Channel(channel.clone()),
+                line,
+                ClientMessage::LRP { channel, message },
+            );
+            self.needs_redraw = true;
+        }
+        // Keep the flood countdowns moving
+        if self.ad_mode || self.ads.rotating || matches!(self.popup, Some(Popup::Ads(_))) {
+            self.needs_redraw = true;
+        }
+    }
+
+    fn popup_key(&mut self, key: KeyCombination, event: KeyEvent) {
+        let Some(popup) = &mut self.popup else {
+            return;
+        };
+        match popup {
+            Popup::Status(editor) => match editor.key(key, event) {
+                Some(StatusAction::Cancel) => self.popup = None,
+                Some(StatusAction::Submit(status)) => {
+                    self.popup = None;
+                    self.set_status(status, true);
+                }
+                None => {}
+            },
+            Popup::Ads(manager) => match manager.key(key, event, &mut self.ads) {
+                Some(AdAction::Close) => {
+                    self.popup = None;
+                    self.save_ads();
+                }
+                Some(AdAction::Post(text)) => {
+                    if let Err(error) = self.post_ad(text) {
+                        self.system_message(error);
+                    }
+                }
+                None => {}
+            },
+            Popup::Moderation(menu) => match menu.key(key) {
+                Some(MenuAction::Cancel) => self.popup = None,
+                Some(MenuAction::Pick(action)) => {
+                    self.popup = None;
+                    if let AppScreen::Chat { text_state, .. } = &mut self.state {
+                        *text_state = TextAreaState::with_text(format!("/{} ", action.command()));
+                    }
+                }
+                None => {}
+            },
+            Popup::Description(editor) => match editor.key(key, event) {
+                Some(DescriptionAction::Cancel) => self.popup = None,
+                Some(DescriptionAction::Save(description)) => {
+                    self.popup = None;
+                    if let AppScreen::Chat { conversations, .. } = &self.state
+                        && let Target::Channel(channel) = &conversations.active().target
+                    {
+                        self.send(ClientMessage::CDS {
+                            channel: channel.clone(),
+                            description,
+                        });
+                    }
+                }
+                None => {}
+            },
+            Popup::Room(dialog) => match dialog.key(key, event) {
+                Some(RoomAction::Cancel) => self.popup = None,
+                Some(RoomAction::Create(room)) => {
+                    self.popup = None;
+                    self.send(ClientMessage::CCR {
+                        channel: room.title.clone(),
+                    });
+                    self.new_room = Some(room);
+                }
+                None => {}
+            },
+            Popup::Report(dialog) => match dialog.key(key, event) {
+                Some(ReportAction::Cancel) => self.popup = None,
+                Some(ReportAction::Send(report)) => {
+                    self.popup = None;
+                    // The alert needs the id of the uploaded log, so it goes out once that's back
+                    if let AppScreen::Chat { ticket, .. } = &self.state {
+                        self.chat_controller.upload_report(
+                            ticket.clone(),
+                            self.character.clone(),
+                            report,
+                        );
+                        self.system_message(String::from("Uploading the log for the report…"));
+                    }
+                }
+                None => {}
+            },
+            Popup::Reports(queue) => match queue.key(key, &self.reports) {
+                Some(QueueAction::Close) => self.popup = None,
+                Some(QueueAction::Claim(callid)) => {
+                    if let Ok(message) =
+                        StaffAction::Confirm.message(&callid.to_string(), &self.character)
+                    {
+                        self.send(message);
+                    }
+                }
+                Some(QueueAction::Dismiss(callid)) => {
+                    self.reports.retain(|report| report.callid != callid);
+                }
+                None => {}
+            },
+            Popup::Invites(list) => match list.key(key, &self.invites) {
+                Some(InviteAction::Close) => self.popup = None,
+                Some(InviteAction::Accept(invite)) => {
+                    self.invites.retain(|pending| *pending != invite);
+                    self.send(ClientMessage::JCH {
+                        channel: invite.channel,
+                    });
+                    if self.invites.is_empty() {
+                        self.popup = None;
+                    }
+                }
+                Some(InviteAction::Decline(invite)) => {
+                    self.invites.retain(|pending| *pending != invite);
+                    if self.invites.is_empty() {
+                        self.popup = None;
+                    }
+                }
+                None => {}
+            },
+            Popup::Links(picker) => match picker.key(key) {
+                Some(PickerAction::Cancel) => self.popup = None,
+                Some(PickerAction::Open(link)) => {
+                    self.popup = None;
+                    self.open_link(link);
+                }
+                None => {}
+            },
+            Popup::Preview(preview) => {
+                if preview.key(key) {
+                    self.popup = None;
+                }
+            }
+            Popup::Console => match self.console.key(key, event) {
+                Some(ConsoleAction::Close) => self.popup = None,
+                Some(ConsoleAction::Dump) => {
+                    let text = match self.console.dump(&self.config.data_dir.join("console")) {
+                        Ok(path) => format!("Dumped to {}", path.display()),
+                        Err(error) => format!("Couldn't dump the console: {}", error),
+                    };
+                    self.console.push(Direction::App, text);
+                }
+                None => {}
+            },
+            Popup::Context(menu) => match menu.key(key) {
+                Some(ContextChoice::Cancel) => self.popup = None,
+                Some(ContextChoice::Pick(action)) => {
+                    let character = menu.character.clone();
+                    self.popup = None;
+                    self.context_action(action, character);
+                }
+                None => {}
+            },
+            Popup::Confirm(confirm) => match confirm.key(key) {
+                Some(Answer::Yes(Confirmed::Send(message))) => {
+                    self.popup = None;
+                    self.send(message);
+                }
+                Some(Answer::Yes(Confirmed::Reconnect)) => {
+                    self.popup = None;
+                    self.reconnect();
+                }
+                Some(Answer::No) => self.popup = None,
+                None => {}
+            },
+            Popup::Search(screen) => match screen.key(key, event) {
+                Some(SearchAction::Cancel) => self.popup = None,
+                Some(SearchAction::Search(query)) => self.search(&query),
+                Some(SearchAction::Jump(result)) => {
+                    self.popup = None;
+                    self.jump_to(result);
+                }
+                None => {}
+            },
+        }
+    }
+
+    fn mouse_event(&mut self, event: MouseEvent) {
+        if !self.mouse {
+            return;
+        }
+        let position = Position::new(event.column, event.row);
+        let click = event.kind == MouseEventKind::Down(MouseButton::Left);
+        match &self.popup {
+            Some(Popup::Context(menu)) if click => {
+                let character = menu.character.clone();
+                let choice = menu.click(position);
+                self.popup = None;
+                if let ContextChoice::Pick(action) = choice {
+                    self.context_action(action, character);
+                }
+                return;
+            }
+            Some(_) => return,
+            None => {}
+        }
+        let AppScreen::Chat {
+            conversations,
+            text_state,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        let areas = self.areas;
+        if areas.scrollback.contains(position) {
+            match event.kind {
+                MouseEventKind::ScrollUp => conversations.active_mut().scroll_up(WHEEL_ROWS),
+                MouseEventKind::ScrollDown => conversations.active_mut().scroll_down(WHEEL_ROWS),
+                _ if click => {
+                    if let Some((area, character)) = conversations
+                        .active()
+                        .viewport
+                        .names
+                        .iter()
+                        .find(|(area, _)| area.contains(position))
+                    {
+                        let (character, position) = (character.clone(), area.as_position());
+                        self.open_context_menu(character, position);
+                    }
+                }
+                _ => {}
+            }
+        } else if areas.members.contains(position) {
+            let conversation = conversations.active_mut();
+            match event.kind {
+                MouseEventKind::ScrollUp => conversation.scroll_members(true, WHEEL_ROWS),
+                MouseEventKind::ScrollDown => conversation.scroll_members(false, WHEEL_ROWS),
+                _ if click => {
+                    if let Some(character) = MemberList::new(conversation, self.layout.members)
+                        .avatars(self.images.shown)
+                        .member_at(areas.members, position)
+                        .map(str::to_owned)
+                    {
+                        let position = Position::new(areas.members.x, position.y);
+                        self.open_context_menu(character, position);
+                    }
+                }
+                _ => {}
+            }
+        } else if areas.tabs.contains(position) {
+            if click
+                && let Some(index) = TabBar::new(conversations).tab_at(position.x - areas.tabs.x)
+            {
+                conversations.select(index);
+            }
+        } else if areas.composer.contains(position) || text_state.selection().is_some() {
+            // Drags keep selecting even once they leave the composer
+            text_state.event(&Event::Mouse(event));
+            if event.kind == MouseEventKind::Up(MouseButton::Left)
+                && let Some(selection) = text_state.selection()
+                && let Err(error) = clipboard::copy(selection)
+            {
+                self.system_message(format!("Couldn't copy the selection: {}", error));
+            }
+        }
+    }
+
+    /// Opens the menu of what can be done to a character, under `position`.
+    fn open_context_menu(&mut self, character: String, position: Position) {
+        let AppScreen::Chat { conversations, .. } = &self.state else {
+            return;
+        };
+        let conversation = conversations.active();
+        let can_kick = matches!(conversation.target, Target::Channel(_))
+            && conversation.is_op(&self.character)
+            && character != self.character;
+        let ignored = self.ignored.contains(&character.to_lowercase());
+        let menu = ContextMenu::new(character, position, ignored, can_kick);
+        self.popup = Some(Popup::Context(menu));
+    }
+
+    fn context_action(&mut self, action: ContextAction, character: String) {
+        let AppScreen::Chat { conversations, .. } = &mut self.state else {
+            return;
+        };
+        match action {
+            ContextAction::Private => conversations.focus(Target::Private(character)),
+            ContextAction::Profile => self.open_link(Link::User(character)),
+            ContextAction::Ignore => self.send(ClientMessage::IGN {
+                action: String::from("add"),
+                character,
+            }),
+            ContextAction::Unignore => self.send(ClientMessage::IGN {
+                action: String::from("delete"),
+                character,
+            }),
+            ContextAction::Kick => {
+                let conversation = conversations.active();
+                let Target::Channel(channel) = &conversation.target else {
+                    return;
+                };
+                let action = ModAction::Kick;
+                match action.message(channel, &character) {
+                    Ok(message) => {
+                        let question = action.confirmation(&conversation.title, &character);
+                        self.popup = Some(Popup::Confirm(Confirm::new(question, message)));
+                    }
+                    Err(error) => self.system_message(error),
+                }
+            }
+        }
+    }
+
+    fn resize(&mut self, split: Split) {
+        if self.layout.resize(split) {
+            self.save_layout();
+        }
+    }
+
+    fn save_layout(&mut self) {
+        let path = self.config.data_dir.join("layout.json");
+        if let Err(error) = storage::save(&path, &self.layout) {
+            self.system_message(format!("Couldn't save the layout: {}", error));
+        }
+    }
+
+    /// Turns capturing the mouse on or off, leaving selection to the terminal while it's off.
+    fn toggle_mouse(&mut self) {
+        self.mouse = !self.mouse;
+        let (result, text) = if self.mouse {
+            (
+                execute!(io::stdout(), EnableMouseCapture),
+                "Mouse on. Use /mouse again to select text in the terminal.",
+            )
+        } else {
+            (
+                execute!(io::stdout(), DisableMouseCapture),
+                "Mouse off, the terminal selects text again.",
+            )
+        };
+        match result {
+            Ok(()) => self.system_message(String::from(text)),
+            Err(error) => self.system_message(format!("Couldn't change mouse capture: {}", error)),
+        }
+    }
+
+    /// Handles keys while searching the active scrollback. Returns whether the key was used.
+    fn scrollback_search_key(&mut self, key: KeyCombination, event: KeyEvent) -> bool {
+        let AppScreen::Chat { conversations, .. } = &mut self.state else {
+            return false;
+        };
+        let conversation = conversations.active_mut();
+        let Some(search) = &mut conversation.search else {
+            return false;
+        };
+        if search.editing {
+            match (key, event.code) {
+                (key!(esc), _) => conversation.search = None,
+                (key!(enter), _) => search.editing = false,
+                (key!(backspace), _) => {
+                    search.query.pop();
+                    conversation.search_edited();
+                }
+                (_, KeyCode::Char(c))
+                    if event.modifiers == KeyModifiers::NONE
+                        || event.modifiers == KeyModifiers::SHIFT =>
+                {
+                    search.query.push(c);
+                    conversation.search_edited();
+                }
+                _ => {}
+            }
+            return true;
+        }
+        match (key, event.code) {
+            (key!(esc), _) => conversation.search = None,
+            (_, KeyCode::Char('n')) => conversation.search_step(true),
+            (_, KeyCode::Char('N')) => conversation.search_step(false),
+            // Scrolling around doesn't end the search
+            (key!(pageup) | key!(pagedown) | key!(home) | key!(end), _) => return false,
+            // Anything else goes back to the composer
+            _ => {
+                conversation.search = None;
+                return false;
+            }
+        }
+        true
+    }
+
+    fn open_search(&mut self, query: &str) {
+        if !matches!(self.state, AppScreen::Chat { .. }) {
+            return;
+        }
+        self.popup = Some(Popup::Search(SearchScreen::new(query)));
+        if !query.is_empty() {
+            self.search(query);
+        }
+    }
+
+    fn search(&mut self, query: &str) {
+        let Some(Popup::Search(screen)) = &mut self.popup else {
+            return;
+        };
+        // The log we're writing to keeps its index up to date as it goes, so use that one
+        let live = match &self.state {
+            AppScreen::Chat { conversations, .. } => conversations.log(),
+            _ => None,
+        };
+        let results = SearchQuery::parse(query).and_then(|query| {
+            match live {
+                Some(log) => log.search(&query, 500),
+                None => ChatLog::new(&self.config.data_dir, &self.character).search(&query, 500),
+            }
+            .map_err(|error| format!("Search failed: {}", error))
+        });
+        screen.set_results(results);
+    }
+
+    /// Opens the conversation a search result is from, scrolled to it.
+    fn jump_to(&mut self, result: SearchResult) {
+        let AppScreen::Chat { conversations, .. } = &mut self.state else {
+            return;
+        };
+        let Some(target) = logs::conversation_target(&result.conversation) else {
+            return;
+        };
+        let day = ChatLog::new(&self.config.data_dir, &self.character)
+            .day(&target, result.date)
+            .unwrap_or_default();
+        conversations.focus(target);
+        conversations.active_mut().reveal(
+            day.into_iter().map(ChatLine::from).collect(),
+            result.entry.timestamp,
+        );
+    }
+
+    fn open_ad_manager(&mut self) {
+        let AppScreen::Chat { conversations, .. } = &self.state else {
+            return;
+        };
+        let channels = conversations
+            .tabs()
+            .iter()
+            .filter_map(|tab| match &tab.target {
+                Target::Channel(channel) => Some(channel.clone()),
+                _ => None,
+            })
+            .collect();
+        self.popup = Some(Popup::Ads(AdManager::new(&self.ads.library, channels)));
+    }
+
+    fn save_ads(&mut self) {
+        if let Err(error) = storage::save(&self.ads_path(), &self.ads.library) {
+            self.console
+                .push(Direction::App, format!("Failed to save ads: {}", error));
+        }
+    }
+
+    fn ads_path(&self) -> std::path::PathBuf {
+        storage::character_dir(&self.config.data_dir, &self.character).join("ads.json")
+    }
+
+    /// Posts an ad in the active channel, if it's a channel and the server would accept it.
+    fn post_ad(&mut self, text: String) -> Result<(), String> {
+        let AppScreen::Chat { conversations, .. } = &mut self.state else {
+            return Ok(());
+        };
+        let Target::Channel(channel) = conversations.active().target.clone() else {
+            return Err(String::from("Ads can only be posted in channels."));
+        };
+        if conversations.active().mode == ChannelMode::ChatOnly {
+            return Err(String::from("This channel doesn't allow ads."));
+        }
+        self.ads.check(&channel, &text)?;
+        self.ads.posted(&channel);
+        conversations.active_mut().scroll_to_bottom();
+        // Like messages, the server doesn't echo our own ads back
+        let line = ChatLine::new(LineKind::Ad, self.character.clone(), text.clone());
+        self.send_shown(
+            Target::Channel(channel.clone()),
+            line,
+            ClientMessage::LRP {
+                channel,
+                message: text,
+            },
+        );
+        Ok(())
+    }
+
+    /// Sets up what depends on the connection, once it's there.
+    fn connected(&mut self) {
+        self.idle.connected();
+        self.ads = AdPoster::new(
+            storage::load(&self.ads_path()),
+            minutes(self.config.ad_interval),
+        );
+        let saved: OwnStatus = storage::load(&self.status_path());
+        if saved != OwnStatus::default() {
+            self.set_status(saved, false);
+        }
+    }
+
+    /// Nothing more can be sent or received. Whatever was still queued is gone with the
+    /// connection, so it's taken off the screen instead of looking like it's on its way.
+    fn connection_lost(&mut self, error: String) {
+        self.sender = None;
+        let AppScreen::Chat { conversations, .. } = &mut self.state else {
+            return;
+        };
+        let mut text = format!("Lost the connection to the server: {}", error);
+        match conversations.drop_pending() {
+            0 => {}
+            1 => text.push_str(". 1 message wasn't sent"),
+            dropped => text.push_str(&format!(". {} messages weren't sent", dropped)),
+        }
+        text.push_str(". Use /reconnect to connect again.");
+        conversations.push(Target::Console, ChatLine::system(text));
+        // Don't take over a popup the user is busy with, the line above says how to get back
+        if self.popup.is_none() {
+            let question = String::from("The connection was lost. Connect again?");
+            self.popup = Some(Popup::Confirm(Confirm::reconnect(question)));
+        }
+    }
+
+    fn reconnect(&mut self) {
+        if self.sender.is_some() {
+            self.system_message(String::from("Already connected."));
+            return;
+        }
+        let AppScreen::Chat { ticket, .. } = &self.state else {
+            return;
+        };
+        self.chat_controller
+            .connect(ticket.clone(), self.character.clone());
+        self.system_message(String::from("Connecting…"));
+    }
+
+    /// Joins the channels that are open again after reconnecting.
+    fn rejoin(&self) {
+        let AppScreen::Chat { conversations, .. } = &self.state else {
+            return;
+        };
+        for conversation in conversations.tabs() {
+            if let Target::Channel(channel) = &conversation.target {
+                self.send(ClientMessage::JCH {
+                    channel: channel.clone(),
+                });
+            }
+        }
+    }
+
+    /// Shows a line in the active conversation.
+    fn system_message(&mut self, text: String) {
+        if let AppScreen::Chat { conversations, .. } = &mut self.state {
+            let target = conversations.active().target.clone();
+            conversations.push(target, ChatLine::system(text));
+        }
+    }
+
+    fn open_moderation_menu(&mut self) {
+        let AppScreen::Chat { conversations, .. } = &self.state else {
+            return;
+        };
+        let conversation = conversations.active();
+        if matches!(conversation.target, Target::Channel(_)) && conversation.is_op(&self.character)
+        {
+            let owner = conversation.is_owner(&self.character);
+            self.popup = Some(Popup::Moderation(ModerationMenu::new(owner)));
+        } else {
+            self.system_message(String::from("You aren't an operator of this channel."));
+        }
+    }
+
+    fn open_description_editor(&mut self) {
+        let AppScreen::Chat { conversations, .. } = &self.state else {
+            return;
+        };
+        let conversation = conversations.active();
+        if matches!(conversation.target, Target::Channel(_)) && conversation.is_op(&self.character)
+        {
+            let editor = DescriptionEditor::new(&conversation.title, &conversation.description);
+            self.popup = Some(Popup::Description(editor));
+        } else {
+            self.system_message(String::from("You aren't an operator of this channel."));
+        }
+    }
+
+    /// Web links open in the browser, sessions are joined.
+    fn open_link(&mut self, link: Link) {
+        if let Link::Session { channel, .. } = link {
+            let target = Target::Channel(channel.clone());
+            if let AppScreen::Chat { conversations, .. } = &mut self.state
+                && conversations.get_mut(&target).is_some()
+            {
+                conversations.focus(target);
+            } else {
+                self.send(ClientMessage::JCH { channel });
+            }
+            return;
+        }
+        let Some(url) = link.url() else {
+            return;
+        };
+        // Links come from anyone in chat, other schemes could start any program
+        if !bbcode::is_web_url(&url) {
+            self.system_message(format!("Not opening {}, only web addresses are.", url));
+        } else if let Err(error) = links::open(&url, self.config.browser.as_deref()) {
+            self.system_message(format!("Couldn't open {}: {}", url, error));
+        }
+    }
+
+    fn open_status_editor(&mut self) {
+        if let AppScreen::Chat { .. } = self.state {
+            self.popup = Some(Popup::Status(StatusEditor::new(&self.status)));
+        }
+    }
+
+    /// Sends STA. Statuses the user picked themselves are remembered for the next launch.
+    fn set_status(&mut self, status: OwnStatus, remember: bool) {
+        if remember {
+            self.idle.status_changed();
+            if let Err(error) = storage::save(&self.status_path(), &status) {
+                self.console
+                    .push(Direction::App, format!("Failed to save status: {}", error));
+            }
+        }
+        self.send(ClientMessage::STA {
+            status: status.availability.status(),
+            statusmsg: status.message.clone(),
+        });
+        self.notifier
+            .set_do_not_disturb(status.availability == Availability::Dnd);
+        self.status = status;
+    }
+
+    fn chat_log(&mut self) -> Option<ChatLog> {
+        if self.config.no_logs {
+            return None;
+        }
+        let log = ChatLog::new(&self.config.data_dir, &self.character);
+        if let Some(days) = self.config.log_retention
+            && let Err(error) = log.prune(days)
+        {
+            self.console.push(
+                Direction::App,
+                format!("Failed to prune old logs: {}", error),
+            );
+        }
+        Some(log)
+    }
+
+    fn status_path(&self) -> std::path::PathBuf {
+        storage::character_dir(&self.config.data_dir, &self.character).join("status.json")
+    }
+
+    fn update_typing(&mut self) {
+        let AppScreen::Chat {
+            text_state,
+            conversations,
+            ..
+        } = &self.state
+        else {
+            return;
+        };
+        let recipient = match &conversations.active().target {
+            Target::Private(character) => Some(character.as_str()),
+            _ => None,
+        };
+        for (character, status) in self.typing.update(recipient, text_state.text()) {
+            self.send(ClientMessage::TPN { character, status });
         }
     }
 
@@ -233,7 +1369,7 @@ impl App {
             AppScreen::Characters { list_state, .. } => {
                 list_state.select_previous();
             }
-            AppScreen::Chat { .. } => {}
+            AppScreen::Chat { conversations, .. } => conversations.prev(),
         }
         self.update_focused();
     }
@@ -246,11 +1382,763 @@ impl App {
             AppScreen::Characters { list_state, .. } => {
                 list_state.select_next();
             }
-            AppScreen::Chat { .. } => {}
+            AppScreen::Chat { conversations, .. } => conversations.next(),
         }
         self.update_focused();
     }
 
+    fn submit(&mut self) {
+        let AppScreen::Chat {
+            text_state,
+            conversations,
+            ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        let input = text_state.take_text();
+        if input.trim().is_empty() {
+            return;
+        }
+        let command = match Command::parse(&input) {
+            Ok(command) => command,
+            Err(error) => {
+                conversations.push(Target::Console, ChatLine::system(error));
+                return;
+            }
+        };
+        let message = match command {
+            Command::Message(text)
+                if self.ad_mode && matches!(conversations.active().target, Target::Channel(_)) =>
+            {
+                if let Err(error) = self.post_ad(text.clone()) {
+                    // Give the ad back, so it isn't lost to a flood timer
+                    if let AppScreen::Chat { text_state, .. } = &mut self.state {
+                        *text_state = TextAreaState::with_text(text);
+                    }
+                    self.system_message(error);
+                }
+                return;
+            }
+            Command::Message(_) if conversations.active().mode == ChannelMode::AdsOnly => {
+                conversations.push(
+                    conversations.active().target.clone(),
+                    ChatLine::system("This channel only allows ads. Use /ad to post one."),
+                );
+                return;
+            }
+            Command::Message(text) => {
+                let target = conversations.active().target.clone();
+                let message = match &target {
+                    Target::Console => {
+                        conversations.push(
+                            Target::Console,
+                            ChatLine::system("Can't send messages to the console."),
+                        );
+                        return;
+                    }
+                    Target::Channel(channel) => ClientMessage::MSG {
+                        channel: channel.clone(),
+                        message: text.clone(),
+                    },
+                    Target::Private(character) => ClientMessage::PRI {
+                        recipient: character.clone(),
+                        message: text.clone(),
+                    },
+                };
+                conversations.active_mut().scroll_to_bottom();
+                // The server doesn't echo our own messages back
+                let line = ChatLine::new(LineKind::Message, self.character.clone(), text);
+                self.send_shown(target, line, message);
+                return;
+            }
+            Command::Join(channel) => ClientMessage::JCH { channel },
+            Command::Close => {
+                let target = conversations.active().target.clone();
+                conversations.close(&target);
+                match target {
+                    Target::Channel(channel) => ClientMessage::LCH { channel },
+                    _ => return,
+                }
+            }
+            Command::Private(character) => {
+                conversations.focus(Target::Private(character));
+                return;
+            }
+            Command::Status(None) => {
+                self.popup = Some(Popup::Status(StatusEditor::new(&self.status)));
+                return;
+            }
+            Command::Status(Some(status)) => {
+                self.set_status(status, true);
+                return;
+            }
+            Command::Search(query) => {
+                self.open_search(&query);
+                return;
+            }
+            // The result comes back as an RLL, for us as well as everyone else
+            Command::Roll(dice) => {
+                let Some(message) = roll_message(conversations, dice) else {
+                    return;
+                };
+                message
+            }
+            Command::Ad(Some(text)) => {
+                if let Err(error) = self.post_ad(text) {
+                    self.system_message(error);
+                }
+                return;
+            }
+            Command::Ad(None) => {
+                self.ad_mode = !self.ad_mode;
+                return;
+            }
+            Command::Ads => {
+                self.open_ad_manager();
+                return;
+            }
+            Command::Cancel => {
+                self.cancel_pending();
+                return;
+            }
+            Command::MakeRoom(name) => {
+                self.popup = Some(Popup::Room(RoomDialog::new(&name)));
+                return;
+            }
+            Command::Code => {
+                let conversation = conversations.active();
+                let target = conversation.target.clone();
+                let text = match &target {
+                    Target::Channel(channel) => {
+                        let link = rooms::session_link(&conversation.title, channel);
+                        // The terminal never says whether it let OSC 52 through, so the link is
+                        // always shown to copy by hand
+                        match clipboard::copy(&link) {
+                            Ok(()) => format!("Sent to clipboard: {}", link),
+                            Err(error) => {
+                                format!("Couldn't reach the clipboard ({}): {}", error, link)
+                            }
+                        }
+                    }
+                    _ => String::from("Only channels have a code."),
+                };
+                conversations.push(target, ChatLine::system(text));
+                return;
+            }
+            Command::Moderate(action, argument) => {
+                let conversation = conversations.active();
+                let target = conversation.target.clone();
+                let Target::Channel(channel) = &target else {
+                    conversations.push(
+                        target,
+                        ChatLine::system("Moderation only works in channels."),
+                    );
+                    return;
+                };
+                let allowed = if action.owner_only() {
+                    conversation.is_owner(&self.character)
+                } else {
+                    conversation.is_op(&self.character)
+                };
+                // Without a new description, edit the current one
+                if allowed && action == ModAction::Description && argument.trim().is_empty() {
+                    self.open_description_editor();
+                    return;
+                }
+                if !allowed {
+                    let error = if action.owner_only() {
+                        "Only the owner of this channel can do that."
+                    } else {
+                        "Only operators of this channel can do that."
+                    };
+                    conversations.push(target, ChatLine::system(error));
+                    return;
+                }
+                match action.message(channel, &argument) {
+                    Ok(message) if action.needs_confirmation() => {
+                        let question = action.confirmation(&conversation.title, &argument);
+                        self.popup = Some(Popup::Confirm(Confirm::new(question, message)));
+                        return;
+                    }
+                    Ok(message) => message,
+                    Err(error) => {
+                        conversations.push(target, ChatLine::system(error));
+                        return;
+                    }
+                }
+            }
+            Command::Report(character) => {
+                let conversation = conversations.active();
+                // In a private conversation, it's most likely about the other side
+                let character = match &conversation.target {
+                    Target::Private(other) if character.is_empty() => other.clone(),
+                    _ => character,
+                };
+                let dialog =
+                    ReportDialog::new(&conversation.title, &character, &conversation.lines);
+                self.popup = Some(Popup::Report(dialog));
+                return;
+            }
+            Command::Preview(url) => {
+                let url = if url.is_empty() {
+                    conversations
+                        .active()
+                        .lines
+                        .iter()
+                        .rev()
+                        .find_map(|line| bbcode::urls(&line.text).pop())
+                } else {
+                    Some(url)
+                };
+                let target = conversations.active().target.clone();
+                let Some(url) = url else {
+                    conversations.push(target, ChatLine::system("No links to preview here."));
+                    return;
+                };
+                if self.previews.blocked(&url) {
+                    let error = format!("Previews of {} are blocked.", url);
+                    conversations.push(target, ChatLine::system(error));
+                    return;
+                }
+                let mut preview = PreviewPopup::new(url.clone());
+                match self.previews.cached(&url) {
+                    Some(path) => preview.loaded(self.images.decode(&path)),
+                    None => {
+                        if !self.chat_controller.fetch(self.previews.request(&url)) {
+                            preview.loaded(Err(String::from(
+                                "Too many downloads at once, try again in a moment.",
+                            )));
+                        }
+                    }
+                }
+                self.popup = Some(Popup::Preview(preview));
+                return;
+            }
+            Command::Layout(argument) => {
+                match self.layout.arrange(&argument) {
+                    Ok(text) => {
+                        self.save_layout();
+                        self.system_message(text);
+                    }
+                    Err(error) => self.system_message(error),
+                }
+                return;
+            }
+            Command::Mouse => {
+                self.toggle_mouse();
+                return;
+            }
+            Command::Invites => {
+                self.popup = Some(Popup::Invites(InviteList::new()));
+                return;
+            }
+            Command::Reconnect => {
+                self.reconnect();
+                return;
+            }
+            Command::Reports => {
+                if self.global_ops.contains(&self.character.to_lowercase()) {
+                    self.popup = Some(Popup::Reports(ReportQueue::new()));
+                } else {
+                    let target = conversations.active().target.clone();
+                    conversations.push(target, ChatLine::system("Unknown command: /reports"));
+                }
+                return;
+            }
+            Command::Staff(action, argument) => {
+                let target = conversations.active().target.clone();
+                // Staff commands don't exist for anyone else
+                if !self.global_ops.contains(&self.character.to_lowercase()) {
+                    let error = format!("Unknown command: /{}", action.command());
+                    conversations.push(target, ChatLine::system(error));
+                    return;
+                }
+                match action.message(&argument, &self.character) {
+                    Ok(message) => {
+                        let question = action.confirmation(&argument);
+                        self.popup = Some(Popup::Confirm(Confirm::new(question, message)));
+                    }
+                    Err(error) => conversations.push(target, ChatLine::system(error)),
+                }
+                return;
+            }
+            Command::Bottle => {
+                let Some(message) = roll_message(conversations, String::from("bottle")) else {
+                    return;
+                };
+                message
+            }
+            Command::Mute => {
+                let target = conversations.active().target.clone();
+                let status = if self.notifier.toggle_mute(target.clone()) {
+                    "Notifications muted for this conversation."
+                } else {
+                    "Notifications unmuted for this conversation."
+                };
+                conversations.push(target, ChatLine::system(status));
+                return;
+            }
+        };
+        self.send(message);
+    }
+
+    fn send(&self, message: ClientMessage) {
+        self.request(Outgoing::Message { id: None, message });
+    }
+
+    /// Sends a message that we show ourselves, marked as pending until it actually went out.
+    fn send_shown(&mut self, target: Target, line: ChatLine, message: ClientMessage) {
+        if self.sender.is_none() {
+            self.system_message(String::from("Not connected, use /reconnect first."));
+            return;
+        }
+        let AppScreen::Chat { conversations, .. } = &mut self.state else {
+            return;
+        };
+        self.last_id += 1;
+        conversations.push_pending(target, line, self.last_id);
+        self.request(Outgoing::Message {
+            id: Some(self.last_id),
+            message,
+        });
+    }
+
+    fn request(&self, request: Outgoing) {
+        if let Some(sender) = &self.sender {
+            // The io thread only goes away when the app is shutting down
+            let _ = sender.blocking_send(request);
+        }
+    }
+
+    /// Asks for our newest pending line in the active conversation to be taken out of the queue.
+    fn cancel_pending(&mut self) {
+        let AppScreen::Chat { conversations, .. } = &self.state else {
+            return;
+        };
+        let pending = conversations
+            .active()
+            .lines
+            .iter()
+            .rev()
+            .find_map(|line| line.pending);
+        match pending {
+            Some(id) => self.request(Outgoing::Cancel(id)),
+            None => self.system_message(String::from("Nothing is waiting to be sent.")),
+        }
+    }
+
+    fn chat(&mut self, message: ServerMessage) {
+        let AppScreen::Chat { conversations, .. } = &mut self.state else {
+            return;
+        };
+        match message {
+            // The server only keeps ignored characters from messaging us privately
+            ServerMessage::MSG { character, .. }
+            | ServerMessage::LRP { character, .. }
+            | ServerMessage::PRI { character, .. }
+            | ServerMessage::RLL { character, .. }
+                if self.ignored.contains(&character.to_lowercase()) => {}
+            ServerMessage::MSG {
+                channel,
+                character,
+                message,
+            } => {
+                let mention = character != self.character && self.mentions.matches(&message);
+                let target = Target::Channel(channel);
+                if mention {
+                    let title = format!(
+                        "{} in {}",
+                        character,
+                        conversations.open(target.clone()).title
+                    );
+                    self.notifier
+                        .notify(Trigger::Mention, &target, &title, &message);
+                }
+                // Prefetching is only a head start, it's fine to skip when busy
+                for request in self.previews.prefetch(&message) {
+                    self.chat_controller.fetch(request);
+                }
+                conversations.push(
+                    target,
+                    ChatLine::new(LineKind::Message, character, message).with_mention(mention),
+                );
+            }
+            ServerMessage::LRP {
+                channel,
+                character,
+                message,
+            } => {
+                let mention = character != self.character && self.mentions.matches(&message);
+                let target = Target::Channel(channel);
+                if mention {
+                    let title = format!(
+                        "{} in {}",
+                        character,
+                        conversations.open(target.clone()).title
+                    );
+                    self.notifier
+                        .notify(Trigger::Mention, &target, &title, &message);
+                }
+                conversations.push(
+                    target,
+                    ChatLine::new(LineKind::Ad, character, message).with_mention(mention),
+                );
+            }
+            ServerMessage::PRI { character, message } => {
+                let mention = self.mentions.matches(&message);
+                let target = Target::Private(character.clone());
+                conversations.open(target.clone()).typing = TypingStatus::Clear;
+                self.notifier
+                    .notify(Trigger::Private, &target, &character, &message);
+                // Prefetching is only a head start, it's fine to skip when busy
+                for request in self.previews.prefetch(&message) {
+                    self.chat_controller.fetch(request);
+                }
+                conversations.push(
+                    target,
+                    ChatLine::new(LineKind::Message, character, message).with_mention(mention),
+                );
+            }
+            ServerMessage::RLL {
+                channel,
+                recipient,
+                character,
+                message,
+                results,
+                rolls,
+                endresult,
+                r#type,
+            } => {
+                let target = match (channel, recipient) {
+                    (Some(channel), _) => Target::Channel(channel),
+                    (None, Some(recipient)) if character == self.character => {
+                        Target::Private(recipient)
+                    }
+                    (None, _) => Target::Private(character.clone()),
+                };
+                let text = if r#type == "dice" {
+                    dice::describe(&rolls, &results, endresult)
+                } else {
+                    // The server's message names who the bottle landed on, after the roller
+                    let message = bbcode::strip(&message);
+                    message
+                        .strip_prefix(&character)
+                        .unwrap_or(&message)
+                        .trim()
+                        .to_owned()
+                };
+                conversations.push(target, ChatLine::new(LineKind::Roll, character, text));
+            }
+            ServerMessage::TPN { character, status } => {
+                // Don't open a tab just because someone started typing
+                if let Some(conversation) = conversations.get_mut(&Target::Private(character)) {
+                    conversation.typing = status;
+                }
+            }
+            ServerMessage::FRL { characters } => {
+                self.friends = characters.into_iter().collect();
+            }
+            ServerMessage::IGN {
+                action,
+                characters,
+                character,
+            } => match (action.as_str(), characters, character) {
+                ("init" | "list", Some(characters), _) => {
+                    self.ignored = characters.iter().map(|name| name.to_lowercase()).collect();
+                }
+                ("add", _, Some(character)) => {
+                    self.ignored.insert(character.to_lowercase());
+                    let text = format!("{} is now ignored.", character);
+                    conversations.push(Target::Console, ChatLine::system(text));
+                }
+                ("delete", _, Some(character)) => {
+                    self.ignored.remove(&character.to_lowercase());
+                    let text = format!("{} is no longer ignored.", character);
+                    conversations.push(Target::Console, ChatLine::system(text));
+                }
+                _ => {}
+            },
+            ServerMessage::NLN { identity, .. } if self.friends.contains(&identity) => {
+                let body = format!("{} is now online.", identity);
+                self.notifier
+                    .notify(Trigger::Friend, &Target::Console, &identity, &body);
+                conversations.push(Target::Console, ChatLine::system(body));
+            }
+            ServerMessage::STA {
+                status, character, ..
+            } if character == self.character => {
+                self.notifier.set_do_not_disturb(status == Status::DND);
+                if let Some(availability) = Availability::from_status(status) {
+                    self.status.availability = availability;
+                }
+            }
+            ServerMessage::JCH {
+                channel,
+                character,
+                title,
+            } if character.identity == self.character => {
+                conversations.open(Target::Channel(channel.clone())).title = title.clone();
+                // Rooms we create get a generated name, so the title is all that tells them apart
+                if self
+                    .new_room
+                    .as_ref()
+                    .is_some_and(|room| room.title == title && channel.starts_with("ADH-"))
+                    && let Some(room) = self.new_room.take()
+                {
+                    if !room.description.is_empty() {
+                        self.send(ClientMessage::CDS {
+                            channel: channel.clone(),
+                            description: room.description,
+                        });
+                    }
+                    // New rooms start out private
+                    if room.public {
+                        self.send(ClientMessage::RST {
+                            channel,
+                            status: String::from("public"),
+                        });
+                    }
+                }
+            }
+            ServerMessage::CIU {
+                sender,
+                title,
+                name,
+            } => {
+                let text = format!("{} invited you to {}. Answer with /invites.", sender, title);
+                conversations.push(Target::Console, ChatLine::system(text));
+                // A newer invite to the same room replaces the old one
+                self.invites.retain(|invite| invite.channel != name);
+                self.invites.push(Invite {
+                    sender,
+                    title,
+                    channel: name,
+                });
+                // Don't take over a popup the user is busy with, /invites gets back to them
+                if self.popup.is_none() {
+                    self.popup = Some(Popup::Invites(InviteList::new()));
+                }
+            }
+            ServerMessage::JCH {
+                channel, character, ..
+            } => {
+                // A late JCH for a channel we already left shouldn't open it again
+                if let Some(conversation) = conversations.get_mut(&Target::Channel(channel)) {
+                    conversation.add_member(character.identity);
+                }
+            }
+            ServerMessage::LCH { channel, character } if character == self.character => {
+                conversations.close(&Target::Channel(channel));
+            }
+            ServerMessage::LCH { channel, character } => {
+                if let Some(conversation) = conversations.get_mut(&Target::Channel(channel)) {
+                    conversation.members.retain(|member| *member != character);
+                }
+            }
+            ServerMessage::FLN { character } => conversations.went_offline(&character),
+            ServerMessage::SYS { message, channel } => {
+                let target = channel.map_or(Target::Console, Target::Channel);
+                conversations.push(target, ChatLine::system(message));
+            }
+            ServerMessage::CDS {
+                channel,
+                description,
+            } => {
+                let target = Target::Channel(channel);
+                // Like everything about a channel, it can arrive just after we left it
+                let Some(conversation) = conversations.get_mut(&target) else {
+                    return;
+                };
+                // The first one arrives when joining, later ones are changes worth pointing out
+                let changed =
+                    conversation.description_received && conversation.description != description;
+                conversation.description = description;
+                conversation.description_received = true;
+                if changed {
+                    conversations.push(
+                        target,
+                        ChatLine::system("The channel description was changed."),
+                    );
+                }
+            }
+            ServerMessage::COL { channel, oplist } => {
+                let Some(conversation) = conversations.get_mut(&Target::Channel(channel)) else {
+                    return;
+                };
+                conversation.ops = oplist;
+                conversation.sort_members();
+            }
+            ServerMessage::COA { character, channel } => {
+                let target = Target::Channel(channel);
+                let Some(conversation) = conversations.get_mut(&target) else {
+                    return;
+                };
+                conversation.ops.push(character.clone());
+                conversation.sort_members();
+                let text = format!("{} is now an operator.", character);
+                conversations.push(target, ChatLine::system(text));
+            }
+            ServerMessage::COR { character, channel } => {
+                let target = Target::Channel(channel);
+                let Some(conversation) = conversations.get_mut(&target) else {
+                    return;
+                };
+                // Keep the owner's slot, even if they're no longer an operator
+                if let Some(index) = conversation
+                    .ops
+                    .iter()
+                    .skip(1)
+                    .position(|op| *op == character)
+                {
+                    conversation.ops.remove(index + 1);
+                }
+                conversation.sort_members();
+                let text = format!("{} is no longer an operator.", character);
+                conversations.push(target, ChatLine::system(text));
+            }
+            ServerMessage::CSO { character, channel } => {
+                let target = Target::Channel(channel);
+                let Some(conversation) = conversations.get_mut(&target) else {
+                    return;
+                };
+                match conversation.ops.first_mut() {
+                    Some(owner) => *owner = character.clone(),
+                    None => conversation.ops.push(character.clone()),
+                }
+                conversation.sort_members();
+                let text = format!("{} is now the owner of this channel.", character);
+                conversations.push(target, ChatLine::system(text));
+            }
+            ServerMessage::CKU {
+                operator,
+                channel,
+                character,
+            } => {
+                let text = format!("{} was kicked by {}.", character, operator);
+                conversations.push(Target::Channel(channel), ChatLine::system(text));
+            }
+            ServerMessage::CBU {
+                operator,
+                channel,
+                character,
+            } => {
+                let text = format!("{} was banned by {}.", character, operator);
+                conversations.push(Target::Channel(channel), ChatLine::system(text));
+            }
+            ServerMessage::CTU {
+                operator,
+                channel,
+                length,
+                character,
+            } => {
+                let text = format!(
+                    "{} was timed out for {} minutes by {}.",
+                    character, length, operator
+                );
+                conversations.push(Target::Channel(channel), ChatLine::system(text));
+            }
+            ServerMessage::ICH {
+                users,
+                channel,
+                mode,
+            } => {
+                let Some(conversation) = conversations.get_mut(&Target::Channel(channel)) else {
+                    return;
+                };
+                conversation.mode = mode;
+                conversation.members = users.into_iter().map(|user| user.identity).collect();
+                conversation.sort_members();
+            }
+            ServerMessage::RMO { mode, channel } => {
+                let target = Target::Channel(channel);
+                let Some(conversation) = conversations.get_mut(&target) else {
+                    return;
+                };
+                conversation.mode = mode;
+                let text = match mode {
+                    ChannelMode::ChatOnly => "This channel now only allows chat.",
+                    ChannelMode::AdsOnly => "This channel now only allows ads.",
+                    ChannelMode::Both => "This channel now allows chat and ads.",
+                };
+                conversations.push(target, ChatLine::system(text));
+            }
+            ServerMessage::VAR { variable, value } => {
+                self.ads.variable(&variable, &value);
+                if let Some(seconds) = value.as_f64()
+                    && variable.ends_with("_flood")
+                {
+                    self.request(Outgoing::Limit { variable, seconds });
+                }
+            }
+            ServerMessage::ERR { message, .. } => {
+                conversations.push(Target::Console, ChatLine::system(message));
+            }
+            ServerMessage::ADL { ops } => {
+                self.global_ops = ops.iter().map(|op| op.to_lowercase()).collect();
+            }
+            ServerMessage::AOP { character } => {
+                self.global_ops.insert(character.to_lowercase());
+                let text = format!("{} is now a global operator.", character);
+                conversations.push(Target::Console, ChatLine::system(text));
+            }
+            ServerMessage::DOP { character } => {
+                self.global_ops.remove(&character.to_lowercase());
+                let text = format!("{} is no longer a global operator.", character);
+                conversations.push(Target::Console, ChatLine::system(text));
+            }
+            ServerMessage::BRO { message } => {
+                let text = format!("Broadcast: {}", message);
+                let target = conversations.active().target.clone();
+                if target != Target::Console {
+                    conversations.push(target, ChatLine::system(text.clone()).with_mention(true));
+                }
+                conversations.push(Target::Console, ChatLine::system(text).with_mention(true));
+            }
+            ServerMessage::SFC {
+                action,
+                moderator,
+                character,
+                timestamp,
+                callid,
+                report,
+                ..
+            } => {
+                let text = match (action.as_str(), moderator) {
+                    ("report", _) => {
+                        let report = report.unwrap_or_default();
+                        let text = format!(
+                            "{} asks for staff: {} (claim with /confirmreport {}, or see /reports)",
+                            character, report, callid
+                        );
+                        self.reports.push(StaffReport {
+                            callid,
+                            character,
+                            report,
+                            timestamp,
+                            claimed_by: None,
+                        });
+                        text
+                    }
+                    ("confirm", Some(moderator)) => {
+                        let text = format!("{} is handling {}'s report.", moderator, character);
+                        if let Some(report) = self
+                            .reports
+                            .iter_mut()
+                            .find(|report| report.callid == callid)
+                        {
+                            report.claimed_by = Some(moderator);
+                        }
+                        text
+                    }
+                    _ => return,
+                };
+                conversations.push(Target::Console, ChatLine::system(text));
+            }
+            _ => {}
+        }
+    }
+
     fn update_focused(&mut self) {
         match &mut self.state {
             AppScreen::Login {
@@ -272,13 +2160,61 @@ impl App {
     }
 }
 
+/// The RLL for dice or a bottle in the active conversation, which goes to the other side of a
+/// private conversation. Says so in the console when the active tab is neither.
+fn roll_message(conversations: &mut Conversations, dice: String) -> Option<ClientMessage> {
+    match &conversations.active().target {
+        Target::Channel(channel) => Some(ClientMessage::RLL {
+            channel: Some(channel.clone()),
+            recipient: None,
+            dice,
+        }),
+        Target::Private(character) => Some(ClientMessage::RLL {
+            channel: None,
+            recipient: Some(character.clone()),
+            dice,
+        }),
+        Target::Console => {
+            conversations.push(
+                Target::Console,
+                ChatLine::system(
+                    "Dice and bottles only work in channels and private conversations.",
+                ),
+            );
+            None
+        }
+    }
+}
+
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(minutes * 60)
+}
+
 #[derive(Debug)]
 pub enum AppEvent {
     Crossterm(Result<crossterm::event::Event, io::Error>),
-    Debug(String),
+    /// A message that was sent to the server, for the developer console.
+    Outbound(String),
     Ticket(Result<Ticket, fchat::ticket::Error>),
-    Connected(tokio::sync::mpsc::Sender<fchat::message::client::Message>),
+    Connected(tokio::sync::mpsc::Sender<Outgoing>),
+    /// Sending to the server failed, so nothing more will be sent.
+    Disconnected(String),
+    /// A message queued with an id went out.
+    Sent(u64),
+    /// A message queued with an id was taken out of the queue before it was sent.
+    Cancelled(u64),
+    /// A report's log was uploaded, or not, so the report can go out.
+    ReportUploaded {
+        report: Report,
+        result: Result<u64, String>,
+    },
+    /// A download requested with `ChatController::fetch` finished.
+    Fetched {
+        url: String,
+        result: Result<Fetched, String>,
+    },
     Chat(ServerMessage),
+    Tick,
     Error(AppError),
 }
 
@@ -287,6 +2223,24 @@ pub enum AppError {
     Connection(fchat::Error),
 }
 
+enum Popup {
+    Status(StatusEditor),
+    Search(SearchScreen),
+    Ads(AdManager),
+    Moderation(ModerationMenu),
+    Confirm(Confirm),
+    Room(RoomDialog),
+    Description(DescriptionEditor),
+    Report(ReportDialog),
+    Reports(ReportQueue),
+    Invites(InviteList),
+    Preview(PreviewPopup),
+    Links(LinkPicker),
+    Context(ContextMenu),
+    /// The developer console, which is kept in `App` so it records while closed.
+    Console,
+}
+
 enum AppScreen {
     Login {
         focus: u8,
@@ -300,5 +2254,6 @@ enum AppScreen {
     Chat {
         ticket: Ticket,
         text_state: TextAreaState,
+        conversations: Conversations,
     },
 }
@@ -0,0 +1,341 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+
+use crate::logs::html_escape;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Link {
+    Url(String),
+    User(String),
+    Session { title: String, channel: String },
+}
+
+impl Link {
+    /// Where the link points on the web, if anywhere.
+    pub fn url(&self) -> Option<String> {
+        match self {
+            Link::Url(url) => Some(url.clone()),
+            Link::User(character) => Some(format!("https://www.f-list.net/c/{}", character)),
+            Link::Session { .. } => None,
+        }
+    }
+}
+
+/// A run of text that shares the same formatting.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub text: String,
+    pub style: Style,
+    pub link: Option<Link>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Tag {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Superscript,
+    Subscript,
+    Spoiler,
+    Color(Color),
+    Url(Option<String>),
+    User,
+    Icon,
+    Eicon,
+    Session(String),
+}
+
+impl Tag {
+    fn parse(name: &str, argument: Option<&str>) -> Option<Tag> {
+        let tag = match (name, argument) {
+            ("b", None) => Tag::Bold,
+            ("i", None) => Tag::Italic,
+            ("u", None) => Tag::Underline,
+            ("s", None) => Tag::Strikethrough,
+            ("sup", None) => Tag::Superscript,
+            ("sub", None) => Tag::Subscript,
+            ("spoiler", None) => Tag::Spoiler,
+            ("color", Some(color)) => Tag::Color(parse_color(color)?),
+            ("url", url) => Tag::Url(url.map(str::to_owned)),
+            ("user", None) => Tag::User,
+            ("icon", None) => Tag::Icon,
+            ("eicon", None) => Tag::Eicon,
+            ("session", Some(title)) => Tag::Session(title.to_owned()),
+            _ => return None,
+        };
+        Some(tag)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Tag::Bold => "b",
+            Tag::Italic => "i",
+            Tag::Underline => "u",
+            Tag::Strikethrough => "s",
+            Tag::Superscript => "sup",
+            Tag::Subscript => "sub",
+            Tag::Spoiler => "spoiler",
+            Tag::Color(_) => "color",
+            Tag::Url(_) => "url",
+            Tag::User => "user",
+            Tag::Icon => "icon",
+            Tag::Eicon => "eicon",
+            Tag::Session(_) => "session",
+        }
+    }
+}
+
+/// The colors FChat allows in `[color]`, and how we show them in the terminal. Black is shown
+/// as dark gray so it stays readable on dark backgrounds.
+const COLORS: [(&str, Color); 12] = [
+    ("red", Color::LightRed),
+    ("blue", Color::LightBlue),
+    ("white", Color::White),
+    ("yellow", Color::Yellow),
+    ("pink", Color::LightMagenta),
+    ("gray", Color::Gray),
+    ("green", Color::LightGreen),
+    ("orange", Color::Indexed(208)),
+    ("purple", Color::Magenta),
+    ("black", Color::DarkGray),
+    ("brown", Color::Indexed(130)),
+    ("cyan", Color::Cyan),
+];
+
+fn parse_color(name: &str) -> Option<Color> {
+    COLORS
+        .iter()
+        .find(|(color_name, _)| color_name.eq_ignore_ascii_case(name))
+        .map(|(_, color)| *color)
+}
+
+fn color_name(color: Color) -> Option<&'static str> {
+    COLORS
+        .iter()
+        .find(|(_, known)| *known == color)
+        .map(|(name, _)| *name)
+}
+
+/// Splits a BBCode message into styled segments. Unknown or unbalanced tags are kept as text,
+/// the same way the official client shows them.
+pub fn parse(input: &str) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut stack: Vec<Tag> = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('[') else {
+            push_text(&mut segments, &stack, rest);
+            break;
+        };
+        push_text(&mut segments, &stack, &rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(']') else {
+            push_text(&mut segments, &stack, rest);
+            break;
+        };
+        let inner = &rest[1..end];
+        if inner.eq_ignore_ascii_case("noparse") {
+            let body = &rest[end + 1..];
+            let (text, after) = match body.find("[/noparse]") {
+                Some(close) => (&body[..close], &body[close + "[/noparse]".len()..]),
+                None => (body, ""),
+            };
+            push_text(&mut segments, &stack, text);
+            rest = after;
+            continue;
+        }
+        if let Some(name) = inner.strip_prefix('/') {
+            let name = name.to_ascii_lowercase();
+            if let Some(position) = stack.iter().rposition(|tag| tag.name() == name) {
+                stack.truncate(position);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        } else {
+            let (name, argument) = match inner.split_once('=') {
+                Some((name, argument)) => (name, Some(argument)),
+                None => (inner, None),
+            };
+            if let Some(tag) = Tag::parse(&name.to_ascii_lowercase(), argument) {
+                stack.push(tag);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        // Not a tag we know about, show the bracket literally
+        push_text(&mut segments, &stack, "[");
+        rest = &rest[1..];
+    }
+    segments
+}
+
+fn push_text(segments: &mut Vec<Segment>, stack: &[Tag], text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let mut style = Style::new();
+    let mut link = None;
+    let mut text = text.to_owned();
+    for tag in stack {
+        match tag {
+            Tag::Bold => style = style.add_modifier(Modifier::BOLD),
+            Tag::Italic => style = style.add_modifier(Modifier::ITALIC),
+            Tag::Underline => style = style.add_modifier(Modifier::UNDERLINED),
+            Tag::Strikethrough => style = style.add_modifier(Modifier::CROSSED_OUT),
+            Tag::Superscript | Tag::Subscript => style = style.add_modifier(Modifier::DIM),
+            Tag::Spoiler => style = style.fg(Color::DarkGray).bg(Color::DarkGray),
+            Tag::Color(color) => style = style.fg(*color),
+            Tag::Url(url) => {
+                style = style
+                    .fg(Color::LightBlue)
+                    .add_modifier(Modifier::UNDERLINED);
+                link = Some(Link::Url(url.clone().unwrap_or_else(|| text.clone())));
+            }
+            Tag::User | Tag::Icon => {
+                style = style.add_modifier(Modifier::BOLD);
+                link = Some(Link::User(text.clone()));
+            }
+            Tag::Eicon => {
+                text = format!(":{}:", text);
+            }
+            Tag::Session(title) => {
+                style = style.fg(Color::LightGreen);
+                link = Some(Link::Session {
+                    title: title.clone(),
+                    channel: text.clone(),
+                });
+                text = format!("#{}", title);
+            }
+        }
+    }
+    if let Some(last) = segments.last_mut()
+        && last.style == style
+        && last.link == link
+    {
+        last.text.push_str(&text);
+        return;
+    }
+    segments.push(Segment { text, style, link });
+}
+
+/// Renders BBCode into styled text, keeping the line breaks of the input.
+pub fn render(input: &str) -> Text<'static> {
+    let mut text = Text::default();
+    let mut line = Line::default();
+    for segment in parse(input) {
+        let mut pieces = segment.text.split('\n');
+        if let Some(first) = pieces.next() {
+            line.push_span(Span::styled(first.to_owned(), segment.style));
+        }
+        for piece in pieces {
+            text.push_line(std::mem::take(&mut line));
+            line.push_span(Span::styled(piece.to_owned(), segment.style));
+        }
+    }
+    text.push_line(line);
+    text
+}
+
+/// The message with all formatting removed.
+pub fn strip(input: &str) -> String {
+    parse(input)
+        .into_iter()
+        .map(|segment| segment.text)
+        .collect()
+}
+
+/// Every link in the message, in order. Bare web addresses count too.
+pub fn links(input: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    for segment in parse(input) {
+        match segment.link {
+            // Formatting inside a link splits it into several segments
+            Some(link) => {
+                if links.last() != Some(&link) {
+                    links.push(link);
+                }
+            }
+            None => links.extend(
+                segment
+                    .text
+                    .split_whitespace()
+                    .filter_map(bare_url)
+                    .map(|url| Link::Url(url.to_owned())),
+            ),
+        }
+    }
+    links
+}
+
+/// Every web address in the message, in order: `[url]` tags and bare addresses alike.
+pub fn urls(input: &str) -> Vec<String> {
+    links(input)
+        .into_iter()
+        .filter_map(|link| match link {
+            Link::Url(url) => Some(url),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The web address at the start of `word`, if it is one.
+pub fn bare_url(word: &str) -> Option<&str> {
+    if !word.starts_with("http://") && !word.starts_with("https://") {
+        return None;
+    }
+    let word = word.split_whitespace().next().unwrap_or_default();
+    // Sentences often end right after an address
+    Some(word.trim_end_matches(['.', ',', '!', '?', ')', ';', ':']))
+}
+
+/// Whether `url` is an http or https address, the only kind that's safe to open or link to.
+pub fn is_web_url(url: &str) -> bool {
+    let url = url.trim_start().to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Converts BBCode to HTML, for exported logs.
+pub fn to_html(input: &str) -> String {
+    let mut html = String::new();
+    for segment in parse(input) {
+        let mut css = Vec::new();
+        let modifiers = segment.style.add_modifier;
+        if modifiers.contains(Modifier::BOLD) {
+            css.push(String::from("font-weight: bold"));
+        }
+        if modifiers.contains(Modifier::ITALIC) {
+            css.push(String::from("font-style: italic"));
+        }
+        let mut decorations = Vec::new();
+        if modifiers.contains(Modifier::UNDERLINED) {
+            decorations.push("underline");
+        }
+        if modifiers.contains(Modifier::CROSSED_OUT) {
+            decorations.push("line-through");
+        }
+        if !decorations.is_empty() {
+            css.push(format!("text-decoration: {}", decorations.join(" ")));
+        }
+        if let Some(color) = segment.style.fg.and_then(color_name) {
+            css.push(format!("color: {}", color));
+        }
+        let mut text = html_escape(&segment.text);
+        if !css.is_empty() {
+            text = format!("<span style=\"{}\">{}</span>", css.join("; "), text);
+        }
+        // Links come from chat, so anything but a web address could run script in the export
+        let url = segment
+            .link
+            .as_ref()
+            .and_then(Link::url)
+            .filter(|url| is_web_url(url));
+        match url {
+            Some(url) => html.push_str(&format!("<a href=\"{}\">{}</a>", html_escape(&url), text)),
+            None => html.push_str(&text),
+        }
+    }
+    html
+}
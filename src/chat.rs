@@ -0,0 +1,643 @@
+use std::fmt;
+
+use chrono::{DateTime, Local};
+use fchat::{ChannelMode, TypingStatus};
+use ratatui::layout::Rect;
+use serde::{Deserialize, Serialize};
+
+use crate::logs::ChatLog;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Target {
+    Console,
+    Channel(String),
+    Private(String),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Message,
+    Emote,
+    Ad,
+    System,
+    /// A dice roll or bottle spin, with the text describing the outcome.
+    Roll,
+}
+
+pub struct ChatLine {
+    pub timestamp: DateTime<Local>,
+    pub kind: LineKind,
+    pub sender: Option<String>,
+    pub text: String,
+    pub mention: bool,
+    /// Set while our own line waits in the outgoing queue, to the id it was queued with.
+    pub pending: Option<u64>,
+}
+
+impl ChatLine {
+    pub fn new(kind: LineKind, sender: String, text: String) -> Self {
+        // Emotes arrive as regular messages, prefixed with "/me"
+        let (kind, text) = match text.strip_prefix("/me") {
+            Some(rest) if kind == LineKind::Message => (LineKind::Emote, rest.trim_start().into()),
+            _ => (kind, text),
+        };
+        ChatLine {
+            timestamp: Local::now(),
+            kind,
+            sender: Some(sender),
+            text,
+            mention: false,
+            pending: None,
+        }
+    }
+
+    pub fn system(text: impl Into<String>) -> Self {
+        ChatLine {
+            timestamp: Local::now(),
+            kind: LineKind::System,
+            sender: None,
+            text: text.into(),
+            mention: false,
+            pending: None,
+        }
+    }
+
+    pub fn with_mention(mut self, mention: bool) -> Self {
+        self.mention = mention;
+        self
+    }
+}
+
+impl fmt::Display for ChatLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] ", self.timestamp.format("%H:%M"))?;
+        let sender = self.sender.as_deref().unwrap_or_default();
+        match self.kind {
+            LineKind::Message => write!(f, "<{}> {}", sender, self.text),
+            LineKind::Emote => write!(f, "* {} {}", sender, self.text),
+            LineKind::Ad => write!(f, "[AD] <{}> {}", sender, self.text),
+            LineKind::System => write!(f, "*** {}", self.text),
+            LineKind::Roll => write!(f, "🎲 {} {}", sender, self.text),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScrollPosition {
+    Bottom,
+    /// Keep the line with this index at the top of the view.
+    Line(usize),
+}
+
+/// How the scrollback was laid out when it was last drawn, so paging knows how lines wrapped.
+#[derive(Clone, Debug, Default)]
+pub struct Viewport {
+    /// The first row of every line.
+    pub first_rows: Vec<usize>,
+    pub rows: usize,
+    pub height: usize,
+    pub top: usize,
+    /// Where eicon tags were drawn, to put the images over them.
+    pub eicons: Vec<(Rect, String)>,
+    /// Where character names were drawn, for clicking them.
+    pub names: Vec<(Rect, String)>,
+}
+
+/// How many rows every line took up in the scrollback, so only new lines have to be wrapped
+/// for the next draw. Cleared whenever it no longer fits the lines.
+#[derive(Default)]
+pub struct RowCounts {
+    pub width: usize,
+    pub eicons: bool,
+    pub filter: LineFilter,
+    pub counts: Vec<usize>,
+}
+
+pub struct ScrollbackSearch {
+    pub query: String,
+    /// Still typing the query, rather than stepping through the hits.
+    pub editing: bool,
+    /// The line of the hit we're on.
+    pub current: Option<usize>,
+}
+
+/// Which lines of a conversation to show. System lines are always shown.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LineFilter {
+    #[default]
+    Both,
+    Chat,
+    Ads,
+}
+
+impl LineFilter {
+    pub fn next(self) -> Self {
+        match self {
+            LineFilter::Both => LineFilter::Chat,
+            LineFilter::Chat => LineFilter::Ads,
+            LineFilter::Ads => LineFilter::Both,
+        }
+    }
+
+    pub fn shows(self, kind: LineKind) -> bool {
+        match (self, kind) {
+            (LineFilter::Both, _) | (_, LineKind::System) => true,
+            (LineFilter::Chat, kind) => kind != LineKind::Ad,
+            (LineFilter::Ads, kind) => kind == LineKind::Ad,
+        }
+    }
+}
+
+pub struct Conversation {
+    pub target: Target,
+    pub title: String,
+    pub lines: Vec<ChatLine>,
+    pub unread: usize,
+    pub mentions: usize,
+    pub scroll: ScrollPosition,
+    /// What the other side of a private conversation last told us about their typing.
+    pub typing: TypingStatus,
+    /// How many of the first lines were restored from the logs rather than received live.
+    pub history: usize,
+    pub viewport: Viewport,
+    pub row_counts: RowCounts,
+    pub search: Option<ScrollbackSearch>,
+    /// Lines that arrived while scrolled up.
+    pub unseen: usize,
+    /// What the channel allows to be sent, from ICH and RMO.
+    pub mode: ChannelMode,
+    pub filter: LineFilter,
+    /// Channel operators from COL, the owner first. The owner is empty if the channel has none.
+    pub ops: Vec<String>,
+    /// Who's in the channel, from ICH, JCH and LCH. Kept in the order the member list shows.
+    pub members: Vec<String>,
+    /// How far the member list is scrolled down.
+    pub members_scroll: usize,
+    /// The channel's BBCode description, from CDS.
+    pub description: String,
+    /// Whether a CDS arrived yet. The first one comes with joining, later ones are changes.
+    pub description_received: bool,
+    /// Whether all of the description is shown, rather than its first line.
+    pub description_expanded: bool,
+}
+
+impl Conversation {
+    pub fn new(target: Target) -> Self {
+        let title = match &target {
+            Target::Console => String::from("Console"),
+            Target::Channel(name) | Target::Private(name) => name.clone(),
+        };
+        Conversation {
+            target,
+            title,
+            lines: Vec::new(),
+            unread: 0,
+            mentions: 0,
+            scroll: ScrollPosition::Bottom,
+            typing: TypingStatus::Clear,
+            history: 0,
+            viewport: Viewport::default(),
+            row_counts: RowCounts::default(),
+            search: None,
+            unseen: 0,
+            mode: ChannelMode::Both,
+            filter: LineFilter::Both,
+            ops: Vec::new(),
+            members: Vec::new(),
+            members_scroll: 0,
+            description: String::new(),
+            description_received: false,
+            description_expanded: false,
+        }
+    }
+
+    fn push(&mut self, line: ChatLine, active: bool) {
+        // Lines the filter hides aren't news
+        if self.filter.shows(line.kind) {
+            if !active {
+                self.unread += 1;
+                if line.mention {
+                    self.mentions += 1;
+                }
+            }
+            if self.scroll != ScrollPosition::Bottom {
+                self.unseen += 1;
+            }
+        }
+        self.lines.push(line);
+    }
+
+    fn mark_read(&mut self) {
+        self.unread = 0;
+        self.mentions = 0;
+    }
+
+    pub fn header(&self) -> String {
+        if let Some(search) = &self.search {
+            return if search.editing {
+                format!("Search: {}_", search.query)
+            } else {
+                format!("Search: {} (n: older, N: newer, Esc: close)", search.query)
+            };
+        }
+        match (&self.target, self.typing) {
+            (Target::Private(character), TypingStatus::Typing) => {
+                format!("{} is typing…", character)
+            }
+            (Target::Private(character), TypingStatus::Paused) => {
+                format!("{} has stopped typing.", character)
+            }
+            (Target::Channel(_), _) => {
+                let mut header = self.title.clone();
+                match self.mode {
+                    ChannelMode::ChatOnly => header.push_str(" [chat only]"),
+                    ChannelMode::AdsOnly => header.push_str(" [ads only]"),
+                    ChannelMode::Both => {}
+                }
+                match self.filter {
+                    LineFilter::Chat => header.push_str(" (showing chat, Alt-F to change)"),
+                    LineFilter::Ads => header.push_str(" (showing ads, Alt-F to change)"),
+                    LineFilter::Both => {}
+                }
+                header
+            }
+            _ => self.title.clone(),
+        }
+    }
+
+    /// Scrolls to the line logged at `timestamp`. If it isn't loaded, the restored history is
+    /// replaced with `day`, the log of the day it was on.
+    pub fn reveal(&mut self, day: Vec<ChatLine>, timestamp: DateTime<Local>) {
+        if let Some(index) = self
+            .lines
+            .iter()
+            .position(|line| line.timestamp == timestamp)
+        {
+            self.scroll = ScrollPosition::Line(index);
+            return;
+        }
+        let live = self.lines.split_off(self.history);
+        let first_live = live.first().map(|line| line.timestamp);
+        // Today's log also holds the live lines, which are already there
+        self.lines = day
+            .into_iter()
+            .filter(|line| first_live.is_none_or(|first| line.timestamp < first))
+            .collect();
+        self.history = self.lines.len();
+        self.row_counts.counts.clear();
+        let index = self
+            .lines
+            .iter()
+            .position(|line| line.timestamp == timestamp);
+        self.lines.extend(live);
+        if let Some(index) = index {
+            self.scroll = ScrollPosition::Line(index);
+        }
+    }
+
+    pub fn is_op(&self, character: &str) -> bool {
+        self.ops.iter().any(|op| op.eq_ignore_ascii_case(character))
+    }
+
+    pub fn is_owner(&self, character: &str) -> bool {
+        self.ops
+            .first()
+            .is_some_and(|owner| owner.eq_ignore_ascii_case(character))
+    }
+
+    /// Where a member goes in the member list: operators first, then by name.
+    fn member_key(&self, member: &str) -> (bool, String) {
+        (!self.is_op(member), member.to_lowercase())
+    }
+
+    /// Adds someone who joined where the member list shows them.
+    pub fn add_member(&mut self, character: String) {
+        if self.members.contains(&character) {
+            return;
+        }
+        let key = self.member_key(&character);
+        let index = self
+            .members
+            .partition_point(|member| self.member_key(member) < key);
+        self.members.insert(index, character);
+    }
+
+    /// Puts the members back in member list order, after all of them or the operators changed.
+    pub fn sort_members(&mut self) {
+        let mut members = std::mem::take(&mut self.members);
+        members.sort_by_cached_key(|member| self.member_key(member));
+        self.members = members;
+    }
+
+    pub fn scroll_members(&mut self, up: bool, rows: usize) {
+        self.members_scroll = if up {
+            self.members_scroll.saturating_sub(rows)
+        } else {
+            (self.members_scroll + rows).min(self.members.len().saturating_sub(1))
+        };
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll = ScrollPosition::Bottom;
+        self.unseen = 0;
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        if !self.lines.is_empty() {
+            self.scroll = ScrollPosition::Line(0);
+        }
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.viewport.height.max(1));
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.viewport.height.max(1));
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        let top = self.viewport.top.saturating_sub(rows);
+        self.scroll_to_row(top);
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        if self.scroll == ScrollPosition::Bottom {
+            return;
+        }
+        let top = self.viewport.top + rows;
+        if top + self.viewport.height >= self.viewport.rows {
+            self.scroll_to_bottom();
+        } else {
+            self.scroll_to_row(top);
+        }
+    }
+
+    /// The lines that were at least partly on screen when the scrollback was last drawn.
+    pub fn visible_lines(&self) -> &[ChatLine] {
+        let rows = &self.viewport.first_rows;
+        let bottom = self.viewport.top + self.viewport.height;
+        let start = rows
+            .partition_point(|row| *row <= self.viewport.top)
+            .saturating_sub(1);
+        let end = rows.partition_point(|row| *row < bottom);
+        self.lines
+            .get(start..end.min(self.lines.len()))
+            .unwrap_or_default()
+    }
+
+    /// Scrolls to the line that the given row of the last drawn layout belongs to.
+    fn scroll_to_row(&mut self, row: usize) {
+        let index = self
+            .viewport
+            .first_rows
+            .partition_point(|first| *first <= row)
+            .saturating_sub(1);
+        if index < self.lines.len() {
+            self.scroll = ScrollPosition::Line(index);
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.search = Some(ScrollbackSearch {
+            query: String::new(),
+            editing: true,
+            current: None,
+        });
+    }
+
+    /// Looks for the query again from the current hit, after it was edited.
+    pub fn search_edited(&mut self) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        search.current = search.current.map(|current| current + 1);
+        self.search_step(true);
+    }
+
+    /// Moves to the next hit, either older or newer than the current one.
+    pub fn search_step(&mut self, older: bool) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let query = search.query.to_lowercase();
+        if query.is_empty() {
+            return;
+        }
+        let matches = |index: &usize| {
+            let line = &self.lines[*index];
+            self.filter.shows(line.kind) && line.to_string().to_lowercase().contains(&query)
+        };
+        let start = search.current.unwrap_or(self.lines.len());
+        let found = if older {
+            (0..start.min(self.lines.len())).rev().find(matches)
+        } else {
+            (start + 1..self.lines.len()).find(matches)
+        };
+        if let Some(index) = found {
+            if let Some(search) = &mut self.search {
+                search.current = Some(index);
+            }
+            self.scroll = ScrollPosition::Line(index);
+        }
+    }
+
+    pub fn jump_to_last_mention(&mut self) {
+        if let Some(index) = self.lines.iter().rposition(|line| line.mention) {
+            self.scroll = ScrollPosition::Line(index);
+        }
+    }
+}
+
+/// The open tabs on the chat screen. The console tab always exists and is never closed.
+pub struct Conversations {
+    tabs: Vec<Conversation>,
+    active: usize,
+    log: Option<ChatLog>,
+    /// How many lines of history to restore from the logs when a tab opens.
+    history: usize,
+}
+
+impl Conversations {
+    pub fn new(log: Option<ChatLog>, history: usize) -> Self {
+        Conversations {
+            tabs: vec![Conversation::new(Target::Console)],
+            active: 0,
+            log,
+            history,
+        }
+    }
+
+    pub fn tabs(&self) -> &[Conversation] {
+        &self.tabs
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn active(&self) -> &Conversation {
+        &self.tabs[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Conversation {
+        &mut self.tabs[self.active]
+    }
+
+    pub fn log(&self) -> Option<&ChatLog> {
+        self.log.as_ref()
+    }
+
+    pub fn get_mut(&mut self, target: &Target) -> Option<&mut Conversation> {
+        self.tabs.iter_mut().find(|tab| &tab.target == target)
+    }
+
+    /// Returns the tab for `target`, opening it in the background if needed.
+    pub fn open(&mut self, target: Target) -> &mut Conversation {
+        let index = self.index_of(target);
+        &mut self.tabs[index]
+    }
+
+    /// Opens the tab for `target` if needed, and switches to it.
+    pub fn focus(&mut self, target: Target) {
+        let index = self.index_of(target);
+        self.select(index);
+    }
+
+    pub fn push(&mut self, target: Target, line: ChatLine) {
+        // Opening the tab restores its history from the log, so that has to happen first
+        let index = self.index_of(target);
+        if let Some(error) = append(&self.log, &self.tabs[index].target, &line) {
+            self.tabs[0].push(error, self.active == 0);
+        }
+        self.tabs[index].push(line, index == self.active);
+    }
+
+    /// Shows a line of ours that's still in the outgoing queue. It's logged once it's sent.
+    pub fn push_pending(&mut self, target: Target, mut line: ChatLine, id: u64) {
+        line.pending = Some(id);
+        let index = self.index_of(target);
+        self.tabs[index].push(line, index == self.active);
+    }
+
+    pub fn sent(&mut self, id: u64) {
+        let Some((tab, index)) = self.find_pending(id) else {
+            return;
+        };
+        let conversation = &mut self.tabs[tab];
+        conversation.lines[index].pending = None;
+        conversation.row_counts.counts.truncate(index);
+        if let Some(error) = append(&self.log, &conversation.target, &conversation.lines[index]) {
+            self.tabs[0].push(error, self.active == 0);
+        }
+    }
+
+    /// Removes a line that was taken out of the outgoing queue, returning it as it was typed.
+    pub fn cancelled(&mut self, id: u64) -> Option<String> {
+        let (tab, index) = self.find_pending(id)?;
+        let line = self.tabs[tab].lines.remove(index);
+        self.tabs[tab].row_counts.counts.truncate(index);
+        Some(match line.kind {
+            LineKind::Emote => format!("/me {}", line.text),
+            _ => line.text,
+        })
+    }
+
+    /// Removes every line that was still waiting to be sent, returning how many there were.
+    pub fn drop_pending(&mut self) -> usize {
+        let mut dropped = 0;
+        for conversation in &mut self.tabs {
+            let Some(first) = conversation
+                .lines
+                .iter()
+                .position(|line| line.pending.is_some())
+            else {
+                continue;
+            };
+            let before = conversation.lines.len();
+            conversation.lines.retain(|line| line.pending.is_none());
+            conversation.row_counts.counts.truncate(first);
+            dropped += before - conversation.lines.len();
+        }
+        dropped
+    }
+
+    fn find_pending(&self, id: u64) -> Option<(usize, usize)> {
+        self.tabs
+            .iter()
+            .enumerate()
+            .find_map(|(tab, conversation)| {
+                let index = conversation
+                    .lines
+                    .iter()
+                    .rposition(|line| line.pending == Some(id))?;
+                Some((tab, index))
+            })
+    }
+
+    /// Takes a character who logged off out of every channel's member list.
+    pub fn went_offline(&mut self, character: &str) {
+        for tab in &mut self.tabs {
+            tab.members.retain(|member| member != character);
+        }
+    }
+
+    pub fn close(&mut self, target: &Target) {
+        if *target == Target::Console {
+            return;
+        }
+        let Some(index) = self.tabs.iter().position(|tab| &tab.target == target) else {
+            return;
+        };
+        self.tabs.remove(index);
+        if self.active >= index {
+            self.select(self.active.saturating_sub(1));
+        }
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.active = index.min(self.tabs.len() - 1);
+        self.tabs[self.active].mark_read();
+    }
+
+    pub fn next(&mut self) {
+        self.select((self.active + 1) % self.tabs.len());
+    }
+
+    pub fn prev(&mut self) {
+        self.select((self.active + self.tabs.len() - 1) % self.tabs.len());
+    }
+
+    fn index_of(&mut self, target: Target) -> usize {
+        match self.tabs.iter().position(|tab| tab.target == target) {
+            Some(index) => index,
+            None => {
+                let mut conversation = Conversation::new(target);
+                if let Some(log) = &self.log
+                    && self.history > 0
+                {
+                    match log.recent(&conversation.target, self.history) {
+                        Ok(entries) => {
+                            conversation.lines = entries.into_iter().map(ChatLine::from).collect();
+                            conversation.history = conversation.lines.len();
+                        }
+                        Err(error) => {
+                            let error = format!("Failed to read history: {}", error);
+                            self.tabs[0].push(ChatLine::system(error), self.active == 0);
+                        }
+                    }
+                }
+                self.tabs.push(conversation);
+                self.tabs.len() - 1
+            }
+        }
+    }
+}
+
+/// Writes a line to the log, returning a line about the failure for the console tab. That tab
+/// isn't logged, so this can't loop.
+fn append(log: &Option<ChatLog>, target: &Target, line: &ChatLine) -> Option<ChatLine> {
+    let error = log.as_ref()?.append(target, line).err()?;
+    Some(ChatLine::system(format!("Failed to write log: {}", error)))
+}
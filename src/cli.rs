@@ -0,0 +1,146 @@
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+use miette::{IntoDiagnostic, WrapErr};
+
+use crate::config::{Config, Subcommand};
+use crate::import::{self, ImportFormat};
+use crate::logs;
+use crate::search::{self, LogIndex, SearchQuery};
+use crate::storage;
+
+/// Runs a subcommand instead of the chat interface.
+pub fn run(command: &Subcommand, config: &Config) -> miette::Result<()> {
+    match command {
+        Subcommand::Export {
+            character,
+            conversation,
+            format,
+            from,
+            to,
+            output,
+        } => {
+            let dir =
+                logs::logs_dir(&config.data_dir, character).join(storage::sanitize(conversation));
+            let mut output: Box<dyn Write> = match output {
+                Some(path) => Box::new(
+                    File::create(path)
+                        .into_diagnostic()
+                        .wrap_err_with(|| format!("Couldn't create {}", path.display()))?,
+                ),
+                None => Box::new(io::stdout().lock()),
+            };
+            logs::export(&dir, *format, *from, *to, &mut output)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Couldn't export logs from {}", dir.display()))?;
+            output.flush().into_diagnostic()
+        }
+        Subcommand::Import {
+            character,
+            format,
+            path,
+            conversation,
+            dry_run,
+        } => {
+            let sources = match (format, conversation) {
+                (ImportFormat::Horizon, _) => import::read_horizon(path),
+                (ImportFormat::Web, Some(conversation)) => {
+                    import::read_web(path, conversation).map(|source| vec![source])
+                }
+                (ImportFormat::Web, None) => {
+                    miette::bail!("Web client exports need --in CONVERSATION")
+                }
+            }
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Couldn't read {}", path.display()))?;
+            let dir = logs::logs_dir(&config.data_dir, character);
+            let mut stdout = io::stdout().lock();
+            let mut total = import::ImportReport::default();
+            let mut index = LogIndex::open(dir.clone());
+            for source in sources {
+                let report = import::merge(&dir, &mut index, source, *dry_run)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("Couldn't write logs to {}", dir.display()))?;
+                writeln!(stdout, "{}", describe_import(&report, *dry_run)).into_diagnostic()?;
+                total.found += report.found;
+                total.duplicates += report.duplicates;
+                total.imported += report.imported;
+                total.unreadable += report.unreadable;
+            }
+            index
+                .save()
+                .into_diagnostic()
+                .wrap_err("Couldn't save the search index")?;
+            total.conversation = String::from("Total");
+            writeln!(stdout, "{}", describe_import(&total, *dry_run)).into_diagnostic()
+        }
+        Subcommand::Search {
+            character,
+            conversation,
+            sender,
+            from,
+            to,
+            limit,
+            text,
+        } => {
+            let query = SearchQuery {
+                conversation: conversation.clone(),
+                sender: sender.clone(),
+                from: *from,
+                to: *to,
+                text: text.join(" "),
+            };
+            let characters = match character {
+                Some(character) => vec![character.clone()],
+                None => all_characters(config)?,
+            };
+            // The limit is for all characters together, newest first
+            let mut results = Vec::new();
+            for character in characters {
+                let mut index = LogIndex::open(logs::logs_dir(&config.data_dir, &character));
+                for result in index.search(&query, *limit).into_diagnostic()? {
+                    results.push((character.clone(), result));
+                }
+            }
+            results.sort_by_key(|(_, result)| Reverse(result.entry.timestamp));
+            results.truncate(*limit);
+            let mut stdout = io::stdout().lock();
+            for (character, result) in results {
+                writeln!(stdout, "{}: {}", character, search::describe(&result))
+                    .into_diagnostic()?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn describe_import(report: &import::ImportReport, dry_run: bool) -> String {
+    format!(
+        "{}: {} found, {} already logged, {} {}, {} unreadable",
+        report.conversation,
+        report.found,
+        report.duplicates,
+        report.imported,
+        if dry_run {
+            "would be imported"
+        } else {
+            "imported"
+        },
+        report.unreadable
+    )
+}
+
+fn all_characters(config: &Config) -> miette::Result<Vec<String>> {
+    let dir = config.data_dir.join("characters");
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut characters = Vec::new();
+    for entry in entries {
+        let entry = entry.into_diagnostic()?;
+        characters.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    characters.sort();
+    Ok(characters)
+}
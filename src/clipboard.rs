@@ -0,0 +1,11 @@
+use std::io::{self, Write};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+
+/// Copies text to the system clipboard through the terminal, with OSC 52. This works over SSH
+/// too, as long as the terminal allows it.
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "\x1b]52;c;{}\x07", BASE64_STANDARD.encode(text))?;
+    stdout.flush()
+}
@@ -0,0 +1,112 @@
+use crate::dice;
+use crate::moderation::ModAction;
+use crate::staff::StaffAction;
+use crate::status::{Availability, OwnStatus};
+
+/// A line submitted from the chat composer.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Message(String),
+    Join(String),
+    Close,
+    Private(String),
+    Mute,
+    /// Without a status, opens the status picker.
+    Status(Option<OwnStatus>),
+    Search(String),
+    /// A validated dice expression.
+    Roll(String),
+    Bottle,
+    /// Posts an ad once. Without text, switches the composer between messages and ads.
+    Ad(Option<String>),
+    /// Opens the ad manager.
+    Ads,
+    /// Takes our last message that wasn't sent yet out of the queue.
+    Cancel,
+    /// A channel operator action, with the rest of the line.
+    Moderate(ModAction, String),
+    /// A global chat operator action, with the rest of the line.
+    Staff(StaffAction, String),
+    /// Opens the room creation dialog, with the name filled in if one was given.
+    MakeRoom(String),
+    /// Copies a link to the active channel.
+    Code,
+    /// Opens the report dialog, with the reported character filled in if one was given.
+    Report(String),
+    /// Opens the queue of reports that came in, for chat operators.
+    Reports,
+    /// Opens the room invites that weren't answered yet.
+    Invites,
+    /// Connects again after the connection was lost.
+    Reconnect,
+    /// Shows the image behind a link, the last one posted in the conversation if none is given.
+    Preview(String),
+    /// Turns mouse support on or off.
+    Mouse,
+    /// Rearranges the panes, or describes how they're arranged without an argument.
+    Layout(String),
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Command, String> {
+        let Some(command) = input.strip_prefix('/') else {
+            return Ok(Command::Message(input.to_owned()));
+        };
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .map(|(name, argument)| (name, argument.trim()))
+            .unwrap_or((command, ""));
+        match name {
+            // Emotes are sent to the server as-is
+            "me" | "me's" => Ok(Command::Message(input.to_owned())),
+            "join" => Ok(Command::Join(required(name, argument)?)),
+            "close" | "part" | "leave" => Ok(Command::Close),
+            "priv" | "pm" => Ok(Command::Private(required(name, argument)?)),
+            "mute" => Ok(Command::Mute),
+            "search" => Ok(Command::Search(argument.to_owned())),
+            "roll" => Ok(Command::Roll(dice::validate(argument)?)),
+            "bottle" => Ok(Command::Bottle),
+            "ad" => Ok(Command::Ad(
+                (!argument.is_empty()).then(|| argument.to_owned()),
+            )),
+            "ads" => Ok(Command::Ads),
+            "cancel" => Ok(Command::Cancel),
+            "makeroom" => Ok(Command::MakeRoom(argument.to_owned())),
+            "code" => Ok(Command::Code),
+            "report" => Ok(Command::Report(argument.to_owned())),
+            "reports" => Ok(Command::Reports),
+            "invites" => Ok(Command::Invites),
+            "reconnect" => Ok(Command::Reconnect),
+            "preview" => Ok(Command::Preview(argument.to_owned())),
+            "mouse" => Ok(Command::Mouse),
+            "layout" => Ok(Command::Layout(argument.to_owned())),
+            "status" if argument.is_empty() => Ok(Command::Status(None)),
+            "status" => {
+                let (availability, message) = argument.split_once(' ').unwrap_or((argument, ""));
+                let availability = Availability::parse(availability)
+                    .ok_or_else(|| format!("Unknown status: {}", availability))?;
+                Ok(Command::Status(Some(OwnStatus {
+                    availability,
+                    message: message.trim().to_owned(),
+                })))
+            }
+            _ => {
+                if let Some(action) = ModAction::from_command(name) {
+                    Ok(Command::Moderate(action, argument.to_owned()))
+                } else if let Some(action) = StaffAction::from_command(name) {
+                    Ok(Command::Staff(action, argument.to_owned()))
+                } else {
+                    Err(format!("Unknown command: /{}", name))
+                }
+            }
+        }
+    }
+}
+
+fn required(name: &str, argument: &str) -> Result<String, String> {
+    if argument.is_empty() {
+        Err(format!("/{} needs an argument", name))
+    } else {
+        Ok(argument.to_owned())
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::Parser;
+
+use crate::import::ImportFormat;
+use crate::logs::ExportFormat;
+use crate::notify;
+use crate::storage;
+
+/// A simple client for FChat.
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Subcommand>,
+
+    /// Extra names that count as a mention of the connected character. Can be given multiple times.
+    #[arg(long = "alias", value_name = "NAME")]
+    pub aliases: Vec<String>,
+
+    /// Events that trigger a notification.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "private,mention,friend"
+    )]
+    pub notify_on: Vec<notify::Trigger>,
+
+    /// How to notify, on top of --notify-command.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "bell")]
+    pub notify_with: Vec<notify::Method>,
+
+    /// Shell command to run for every notification. The title and body are passed in the
+    /// RSFCHAT_TITLE and RSFCHAT_BODY environment variables.
+    #[arg(long, value_name = "COMMAND")]
+    pub notify_command: Option<String>,
+
+    /// Minutes without keyboard input before switching to away. Disabled by default.
+    #[arg(long, value_name = "MINUTES")]
+    pub auto_away: Option<u64>,
+
+    /// Minutes the terminal can be unfocused before switching to away. Use 0 to switch as soon
+    /// as focus is lost. Disabled by default.
+    #[arg(long, value_name = "MINUTES")]
+    pub away_when_unfocused: Option<u64>,
+
+    /// Status message to use while automatically away. Keeps the current one by default.
+    #[arg(long, value_name = "MESSAGE")]
+    pub away_message: Option<String>,
+
+    /// Where to keep saved state, like the last status of every character.
+    #[arg(long, value_name = "DIR", default_value_os_t = storage::default_data_dir())]
+    pub data_dir: PathBuf,
+
+    /// Don't write conversations to disk.
+    #[arg(long)]
+    pub no_logs: bool,
+
+    /// Delete logs older than this many days when connecting. Logs are kept forever by default.
+    #[arg(long, value_name = "DAYS")]
+    pub log_retention: Option<u64>,
+
+    /// Minutes between ads posted by the rotation in each channel. The server's own limit
+    /// always applies.
+    #[arg(long, value_name = "MINUTES", default_value_t = 15)]
+    pub ad_interval: u64,
+
+    /// Lines of logged history to show when a conversation is opened. 0 disables it.
+    #[arg(long, value_name = "LINES", default_value_t = 50)]
+    pub history: usize,
+
+    /// Show avatars and eicons as text only, without downloading them. Alt-I hides them for a
+    /// while instead.
+    #[arg(long)]
+    pub no_images: bool,
+
+    /// Largest image a link preview downloads, in KiB.
+    #[arg(long, value_name = "KIB", default_value_t = 5120)]
+    pub preview_max_size: u64,
+
+    /// Never preview links to this domain or its subdomains. Can be given multiple times.
+    #[arg(long = "preview-block", value_name = "DOMAIN")]
+    pub preview_blocklist: Vec<String>,
+
+    /// Download links as they're posted, so previews open right away. Links are only
+    /// downloaded when previewed by default.
+    #[arg(long)]
+    pub auto_preview: bool,
+
+    /// Shell command that opens links, which get passed in the RSFCHAT_URL environment
+    /// variable. Uses the system's default browser otherwise.
+    #[arg(long, value_name = "COMMAND")]
+    pub browser: Option<String>,
+
+    /// Don't make links clickable, even if the terminal supports it.
+    #[arg(long)]
+    pub no_hyperlinks: bool,
+
+    /// Use the mouse for tabs, scrolling, names and the composer. Takes over the terminal's own
+    /// text selection, toggle it with /mouse.
+    #[arg(long)]
+    pub mouse: bool,
+
+    /// Frames the developer console (F12) keeps.
+    #[arg(long, value_name = "FRAMES", default_value_t = 1000)]
+    pub console_size: usize,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Subcommand {
+    /// Export the logs of a conversation as plain text or HTML.
+    Export {
+        /// The character the logs belong to.
+        character: String,
+        /// The conversation to export: #channel or @character.
+        conversation: String,
+        #[arg(long, value_enum, default_value = "text")]
+        format: ExportFormat,
+        /// First day to export, as YYYY-MM-DD.
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last day to export, as YYYY-MM-DD.
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Write to this file instead of standard output.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Import logs from F-Chat Horizon or the official web client. Entries that are already
+    /// logged are skipped.
+    Import {
+        /// The character to import the logs for.
+        character: String,
+        /// The client the logs come from.
+        #[arg(long, value_enum)]
+        format: ImportFormat,
+        /// A Horizon logs directory or conversation file, or a web client export or directory of
+        /// exports.
+        path: PathBuf,
+        /// The conversation web client exports belong to: #channel or @character. Horizon logs
+        /// are named after their conversation.
+        #[arg(long = "in", value_name = "CONVERSATION")]
+        conversation: Option<String>,
+        /// Only report what would be imported, without writing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Search the logs.
+    Search {
+        /// Only search the logs of this character. Searches all of them by default.
+        #[arg(long)]
+        character: Option<String>,
+        /// Only search this conversation: #channel or @character.
+        #[arg(long = "in", value_name = "CONVERSATION")]
+        conversation: Option<String>,
+        /// Only find messages sent by this character.
+        #[arg(long = "sender", value_name = "CHARACTER")]
+        sender: Option<String>,
+        /// First day to search, as YYYY-MM-DD.
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last day to search, as YYYY-MM-DD.
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Maximum number of results, newest first.
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+        /// Text to look for, case-insensitively.
+        #[arg(default_value = "")]
+        text: Vec<String>,
+    },
+}
@@ -0,0 +1,58 @@
+use crokey::{KeyCombination, key};
+use fchat::ClientMessage;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, Paragraph, Wrap},
+};
+
+/// What happens when the question is answered with yes.
+pub enum Confirmed {
+    Send(ClientMessage),
+    Reconnect,
+}
+
+pub enum Answer {
+    Yes(Confirmed),
+    No,
+}
+
+/// Asks before doing something that's hard to take back, or that shouldn't happen on its own.
+pub struct Confirm {
+    question: String,
+    action: Option<Confirmed>,
+}
+
+impl Confirm {
+    pub fn new(question: String, message: ClientMessage) -> Self {
+        Confirm {
+            question,
+            action: Some(Confirmed::Send(message)),
+        }
+    }
+
+    pub fn reconnect(question: String) -> Self {
+        Confirm {
+            question,
+            action: Some(Confirmed::Reconnect),
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination) -> Option<Answer> {
+        match key {
+            key!(y) | key!(enter) => self.action.take().map(Answer::Yes),
+            key!(n) | key!(esc) => Some(Answer::No),
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame, area: Rect) {
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(self.question.as_str())
+                .wrap(Wrap { trim: true })
+                .block(Block::bordered().title("Confirm (y: yes, n: no)")),
+            area,
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt::{Debug, Write as _};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use crokey::{KeyCombination, key};
+use crossterm::event::KeyEvent;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+};
+use ratatui_macros::vertical;
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use serde::Serialize;
+use serde_json::Value;
+use tui_prompts::{Prompt, State, TextPrompt, TextState};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// From the server.
+    In,
+    /// To the server.
+    Out,
+    /// Something that happened in the client.
+    App,
+}
+
+impl Direction {
+    fn arrow(self) -> &'static str {
+        match self {
+            Direction::In => "<<",
+            Direction::Out => ">>",
+            Direction::App => "--",
+        }
+    }
+}
+
+/// A protocol message the way it goes over the socket: the command, then its body as JSON.
+pub fn wire_text<T: Serialize + Debug>(message: &T) -> String {
+    match serde_json::to_value(message) {
+        // Commands without a body, like PIN
+        Ok(Value::String(command)) => command,
+        Ok(Value::Object(object)) if object.len() == 1 => object
+            .into_iter()
+            .map(|(command, body)| format!("{} {}", command, body))
+            .collect(),
+        _ => format!("{:?}", message),
+    }
+}
+
+/// One protocol frame, or a note from the client.
+struct ConsoleLine {
+    timestamp: DateTime<Local>,
+    direction: Direction,
+    /// The protocol command, like MSG. Notes use the name of the event.
+    command: String,
+    text: String,
+}
+
+impl ConsoleLine {
+    fn new(direction: Direction, text: String) -> Self {
+        let command = text
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        ConsoleLine {
+            timestamp: Local::now(),
+            direction,
+            command,
+            text,
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.timestamp.format("%H:%M:%S%.3f"),
+            self.direction.arrow(),
+            self.text
+        )
+    }
+}
+
+pub enum ConsoleAction {
+    Close,
+    Dump,
+}
+
+/// The last frames sent and received, kept in the background and shown over everything when
+/// opened.
+pub struct DevConsole {
+    frames: AllocRingBuffer<ConsoleLine>,
+    /// Commands to show, uppercased. Shows all of them when empty.
+    filter: Vec<String>,
+    input: TextState<'static>,
+    /// Whether the filter is being typed in.
+    editing: bool,
+    /// Frames between the bottom of the console and the newest shown frame.
+    scroll: usize,
+    /// How many frames fit, as of the last draw.
+    height: usize,
+}
+
+impl DevConsole {
+    pub fn new(size: usize) -> Self {
+        DevConsole {
+            frames: AllocRingBuffer::new(size.max(1)),
+            filter: Vec::new(),
+            input: TextState::new(),
+            editing: false,
+            scroll: 0,
+            height: 0,
+        }
+    }
+
+    pub fn push(&mut self, direction: Direction, text: String) {
+        let line = ConsoleLine::new(direction, text);
+        // Stay on the same frames while scrolled up
+        if self.scroll > 0 && self.shows(&line) {
+            self.scroll += 1;
+        }
+        self.frames.push(line);
+    }
+
+    fn shows(&self, line: &ConsoleLine) -> bool {
+        self.filter.is_empty()
+            || self
+                .filter
+                .iter()
+                .any(|command| line.command.eq_ignore_ascii_case(command))
+    }
+
+    fn shown(&self) -> Vec<&ConsoleLine> {
+        self.frames.iter().filter(|line| self.shows(line)).collect()
+    }
+
+    pub fn key(&mut self, key: KeyCombination, event: KeyEvent) -> Option<ConsoleAction> {
+        if self.editing {
+            match key {
+                key!(enter) => {
+                    self.filter = self
+                        .input
+                        .value()
+                        .split([' ', ','])
+                        .filter(|command| !command.is_empty())
+                        .map(str::to_uppercase)
+                        .collect();
+                    self.scroll = 0;
+                    self.editing = false;
+                    self.input.blur();
+                }
+                key!(esc) => {
+                    self.input = TextState::new();
+                    self.input.value_mut().push_str(&self.filter.join(" "));
+                    self.editing = false;
+                }
+                _ => {
+                    self.input.handle_key_event(event);
+                }
+            }
+            return None;
+        }
+        let page = self.height.max(1);
+        // Scrolled all the way up, the oldest frame is at the top and the console is still full
+        let top = self.shown().len().saturating_sub(page);
+        match key {
+            key!(esc) | key!(f12) => return Some(ConsoleAction::Close),
+            key!(d) => return Some(ConsoleAction::Dump),
+            key!('/') => {
+                self.editing = true;
+                self.input.focus();
+            }
+            key!(up) | key!(k) => self.scroll = (self.scroll + 1).min(top),
+            key!(down) | key!(j) => self.scroll = self.scroll.saturating_sub(1),
+            key!(pageup) => self.scroll = (self.scroll + page).min(top),
+            key!(pagedown) => self.scroll = self.scroll.saturating_sub(page),
+            key!(home) => self.scroll = top,
+            key!(end) => self.scroll = 0,
+            _ => {}
+        }
+        None
+    }
+
+    pub fn paste(&mut self, data: &str) {
+        if self.editing {
+            self.input.value_mut().push_str(data);
+        }
+    }
+
+    /// Writes every frame that's kept, filtered or not, to a new file in `dir`.
+    pub fn dump(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "console-{}.log",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        let mut text = String::new();
+        for line in self.frames.iter() {
+            let _ = writeln!(text, "{}", line.describe());
+        }
+        fs::write(&path, text)?;
+        Ok(path)
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(
+            "Developer console (/: filter, d: dump to file, Up/Down/PgUp/PgDn: scroll, F12: close)",
+        );
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        let [filter_area, frames_area] = vertical![==1, *=1].areas(inner);
+        if self.editing || !self.filter.is_empty() {
+            TextPrompt::new("Commands".into()).draw(frame, filter_area, &mut self.input);
+        } else {
+            frame.render_widget(
+                format!("{} frames, all commands shown", self.frames.len()).dark_gray(),
+                filter_area,
+            );
+        }
+        self.height = frames_area.height as usize;
+        let shown = self.shown();
+        let end = shown.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(self.height);
+        let lines: Vec<Line> = shown[start..end]
+            .iter()
+            .map(|line| {
+                let color = match line.direction {
+                    Direction::In => Color::Cyan,
+                    Direction::Out => Color::Green,
+                    Direction::App => Color::DarkGray,
+                };
+                Line::from(Span::raw(line.describe()).fg(color))
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines), frames_area);
+    }
+}
@@ -0,0 +1,143 @@
+use crokey::{KeyCombination, key};
+use ratatui::{
+    Frame,
+    layout::{Position, Rect},
+    widgets::{Block, Clear, List, ListState},
+};
+use ratatui_macros::horizontal;
+
+/// Columns of the avatar in front of the actions, which is half as many rows high.
+const AVATAR_WIDTH: u16 = 8;
+
+/// What can be done to a character from their name in the scrollback.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ContextAction {
+    Private,
+    Profile,
+    Ignore,
+    Unignore,
+    Kick,
+}
+
+impl ContextAction {
+    fn label(self) -> &'static str {
+        match self {
+            ContextAction::Private => "Private message",
+            ContextAction::Profile => "Open profile",
+            ContextAction::Ignore => "Ignore",
+            ContextAction::Unignore => "Stop ignoring",
+            ContextAction::Kick => "Kick from channel",
+        }
+    }
+}
+
+pub enum ContextChoice {
+    Cancel,
+    Pick(ContextAction),
+}
+
+/// A small menu next to a character's name, opened by clicking it.
+pub struct ContextMenu {
+    pub character: String,
+    position: Position,
+    actions: Vec<ContextAction>,
+    list_state: ListState,
+    /// Where it was last drawn, for clicks.
+    area: Rect,
+    /// Where the actions were last drawn, inside `area`.
+    list_area: Rect,
+}
+
+impl ContextMenu {
+    pub fn new(character: String, position: Position, ignored: bool, can_kick: bool) -> Self {
+        let mut actions = vec![ContextAction::Private, ContextAction::Profile];
+        actions.push(if ignored {
+            ContextAction::Unignore
+        } else {
+            ContextAction::Ignore
+        });
+        if can_kick {
+            actions.push(ContextAction::Kick);
+        }
+        ContextMenu {
+            character,
+            position,
+            actions,
+            list_state: ListState::default().with_selected(Some(0)),
+            area: Rect::default(),
+            list_area: Rect::default(),
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination) -> Option<ContextChoice> {
+        match key {
+            key!(esc) => return Some(ContextChoice::Cancel),
+            key!(enter) => {
+                let index = self.list_state.selected()?;
+                return Some(ContextChoice::Pick(*self.actions.get(index)?));
+            }
+            key!(up) => self.list_state.select_previous(),
+            key!(down) => self.list_state.select_next(),
+            _ => {}
+        }
+        None
+    }
+
+    /// Clicking an item picks it, clicking anywhere else closes the menu.
+    pub fn click(&self, position: Position) -> ContextChoice {
+        if !self.area.contains(position) {
+            return ContextChoice::Cancel;
+        }
+        if !self.list_area.contains(position) {
+            return ContextChoice::Cancel;
+        }
+        let index = (position.y - self.list_area.y) as usize;
+        match self.actions.get(index) {
+            Some(action) => ContextChoice::Pick(*action),
+            None => ContextChoice::Cancel,
+        }
+    }
+
+    /// Draws the menu below the name, kept inside `bounds`. With `avatar`, room is left for the
+    /// character's avatar in front of the actions, and where that is gets returned.
+    pub fn draw(&mut self, frame: &mut Frame, bounds: Rect, avatar: bool) -> Rect {
+        let avatar_width = if avatar { AVATAR_WIDTH + 1 } else { 0 };
+        let avatar_height = if avatar { AVATAR_WIDTH / 2 } else { 0 };
+        let width = self
+            .actions
+            .iter()
+            .map(|action| action.label().len())
+            .chain([self.character.len()])
+            .max()
+            .unwrap_or_default() as u16
+            + 4
+            + avatar_width;
+        let height = (self.actions.len() as u16).max(avatar_height) + 2;
+        let x = self
+            .position
+            .x
+            .min(bounds.right().saturating_sub(width))
+            .max(bounds.x);
+        let y = (self.position.y + 1)
+            .min(bounds.bottom().saturating_sub(height))
+            .max(bounds.y);
+        self.area = Rect::new(x, y, width, height).intersection(bounds);
+        let block = Block::bordered().title(self.character.as_str());
+        let [avatar_area, _, list_area] =
+            horizontal![==avatar_width.saturating_sub(1), ==avatar_width.min(1), *=1]
+                .areas(block.inner(self.area));
+        self.list_area = list_area;
+        let items = self.actions.iter().map(|action| action.label());
+        frame.render_widget(Clear, self.area);
+        frame.render_widget(block, self.area);
+        frame.render_stateful_widget(
+            List::new(items).highlight_symbol("> "),
+            list_area,
+            &mut self.list_state,
+        );
+        Rect {
+            height: avatar_height.min(avatar_area.height),
+            ..avatar_area
+        }
+    }
+}
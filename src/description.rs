@@ -0,0 +1,65 @@
+use crokey::{KeyCombination, key};
+use crossterm::event::{Event, KeyEvent};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, Paragraph, Wrap},
+};
+use ratatui_macros::horizontal;
+
+use crate::bbcode;
+use crate::widgets::{TextArea, TextAreaState};
+
+pub enum DescriptionAction {
+    Cancel,
+    Save(String),
+}
+
+/// Popup for operators to rewrite a channel's description, with a BBCode preview.
+pub struct DescriptionEditor {
+    title: String,
+    text: TextAreaState,
+}
+
+impl DescriptionEditor {
+    pub fn new(title: &str, description: &str) -> Self {
+        DescriptionEditor {
+            title: title.to_owned(),
+            text: TextAreaState::with_text(description.to_owned()),
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination, event: KeyEvent) -> Option<DescriptionAction> {
+        match key {
+            key!(esc) => return Some(DescriptionAction::Cancel),
+            // Enter makes a new line, so saving needs a key of its own
+            key!(ctrl - s) => {
+                return Some(DescriptionAction::Save(self.text.text().trim().to_owned()));
+            }
+            _ => self.text.event(&Event::Key(event)),
+        }
+        None
+    }
+
+    pub fn paste(&mut self, data: &str) {
+        self.text.event(&Event::Paste(data.to_owned()));
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(format!(
+            "Description of {} (Ctrl-S: save, Esc: cancel)",
+            self.title
+        ));
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        let [editor_area, preview_area] = horizontal![*=1, *=1].areas(inner);
+        frame.render_stateful_widget_ref(TextArea::new(), editor_area, &mut self.text);
+        frame.render_widget(
+            Paragraph::new(bbcode::render(self.text.text()))
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title("Preview")),
+            preview_area,
+        );
+    }
+}
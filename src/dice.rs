@@ -0,0 +1,104 @@
+/// Limits of the server's dice roller. Anything outside of them comes back as an error.
+const MAX_TERMS: usize = 20;
+const MAX_DICE: u32 = 9;
+const MAX_SIDES: u32 = 500;
+const MAX_NUMBER: u32 = 10000;
+
+/// What the server rolls when it isn't given an expression.
+const DEFAULT: &str = "1d10";
+
+/// Checks a dice expression before sending it, so mistakes show up right away instead of as an
+/// error from the server. Expressions are dice like `2d6` and plain numbers, joined by `+` or
+/// `-`. Returns the expression the way it should be sent.
+pub fn validate(expression: &str) -> Result<String, String> {
+    let expression: String = expression
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    if expression.is_empty() {
+        return Ok(DEFAULT.to_owned());
+    }
+    let terms: Vec<&str> = expression.split(['+', '-']).collect();
+    if terms.len() > MAX_TERMS {
+        return Err(format!("Can't roll more than {} terms at once", MAX_TERMS));
+    }
+    for term in terms {
+        if term.is_empty() {
+            return Err(format!("Missing a term in {}", expression));
+        }
+        match term.split_once('d') {
+            Some((count, sides)) => {
+                let count = number(count, term)?;
+                let sides = number(sides, term)?;
+                if !(1..=MAX_DICE).contains(&count) {
+                    return Err(format!("Can only roll 1 to {} dice at once", MAX_DICE));
+                }
+                if !(2..=MAX_SIDES).contains(&sides) {
+                    return Err(format!("Dice need 2 to {} sides", MAX_SIDES));
+                }
+            }
+            None => {
+                if number(term, term)? > MAX_NUMBER {
+                    return Err(format!("Numbers can't be larger than {}", MAX_NUMBER));
+                }
+            }
+        }
+    }
+    Ok(expression)
+}
+
+fn number(digits: &str, term: &str) -> Result<u32, String> {
+    digits
+        .parse()
+        .map_err(|_| format!("Not a die or a number: {}", term))
+}
+
+/// The text of a roll for the scrollback, from the parts of an RLL, for example
+/// `rolls 2d6 +3: 7 + 3 = 10`.
+pub fn describe(rolls: &[String], results: &[i64], total: i64) -> String {
+    let results: Vec<String> = results.iter().map(i64::to_string).collect();
+    format!(
+        "rolls {}: {} = {}",
+        rolls.join(" "),
+        results.join(" + "),
+        total
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_valid_expressions() {
+        assert_eq!(validate(""), Ok(String::from("1d10")));
+        assert_eq!(validate(" 2D6 + 3 "), Ok(String::from("2d6+3")));
+        assert_eq!(validate("9d500-10000"), Ok(String::from("9d500-10000")));
+    }
+
+    #[test]
+    fn limits_the_number_of_dice() {
+        assert!(validate("1d6").is_ok());
+        assert!(validate("9d6").is_ok());
+        assert!(validate("0d6").is_err());
+        assert!(validate("10d6").is_err());
+    }
+
+    #[test]
+    fn limits_the_size_of_dice_and_numbers() {
+        assert!(validate("1d2").is_ok());
+        assert!(validate("1d1").is_err());
+        assert!(validate("1d501").is_err());
+        assert!(validate("10001").is_err());
+        assert!(validate("1d99999999999").is_err());
+    }
+
+    #[test]
+    fn limits_the_number_of_terms() {
+        assert!(validate(&vec!["1"; 20].join("+")).is_ok());
+        assert!(validate(&vec!["1"; 21].join("+")).is_err());
+        assert!(validate("1d6+").is_err());
+        assert!(validate("d6").is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::PathBuf;
+
+use reqwest::header::CONTENT_TYPE;
+
+/// A download into the on-disk cache.
+#[derive(Clone, Debug)]
+pub struct FetchRequest {
+    pub url: String,
+    pub path: PathBuf,
+    /// Downloads that turn out bigger than this are abandoned.
+    pub max_bytes: u64,
+    /// Abandons downloads the server says aren't images.
+    pub images_only: bool,
+}
+
+/// A finished download.
+#[derive(Clone, Debug)]
+pub struct Fetched {
+    pub path: PathBuf,
+    pub content_type: Option<String>,
+}
+
+/// Downloads `request.url` to `request.path`, stopping as soon as it's over the size limit.
+pub async fn download(client: &reqwest::Client, request: &FetchRequest) -> Result<Fetched, String> {
+    let mut response = client
+        .get(&request.url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| error.to_string())?;
+    let too_big = || format!("Larger than {} KiB", request.max_bytes / 1024);
+    if response
+        .content_length()
+        .is_some_and(|length| length > request.max_bytes)
+    {
+        return Err(too_big());
+    }
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    if request.images_only
+        && let Some(content_type) = &content_type
+        && !content_type.starts_with("image/")
+    {
+        return Err(format!("Not an image, but {}", content_type));
+    }
+    // Servers don't always say how long the body is, so count while reading it
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|error| error.to_string())? {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > request.max_bytes {
+            return Err(too_big());
+        }
+    }
+    if let Some(parent) = request.path.parent() {
+        fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+    }
+    fs::write(&request.path, body).map_err(|error| error.to_string())?;
+    Ok(Fetched {
+        path: request.path.clone(),
+        content_type,
+    })
+}
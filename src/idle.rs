@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+use crate::status::{Availability, OwnStatus};
+
+/// Switches us to away after a while without input or terminal focus, and back once the user
+/// returns.
+pub struct IdleTracker {
+    idle_timeout: Option<Duration>,
+    unfocused_timeout: Option<Duration>,
+    message: Option<String>,
+    last_input: Instant,
+    /// When the terminal lost focus, if it currently doesn't have it.
+    unfocused_since: Option<Instant>,
+    /// The status to restore, if we're currently auto-away.
+    restore: Option<OwnStatus>,
+}
+
+impl IdleTracker {
+    pub fn new(
+        idle_timeout: Option<Duration>,
+        unfocused_timeout: Option<Duration>,
+        message: Option<String>,
+    ) -> Self {
+        IdleTracker {
+            idle_timeout,
+            unfocused_timeout,
+            message,
+            last_input: Instant::now(),
+            unfocused_since: None,
+            restore: None,
+        }
+    }
+
+    /// Returns the status to go back to, if we were auto-away.
+    pub fn input(&mut self) -> Option<OwnStatus> {
+        self.last_input = Instant::now();
+        self.restore.take()
+    }
+
+    pub fn focus_lost(&mut self) {
+        self.unfocused_since.get_or_insert_with(Instant::now);
+    }
+
+    /// Returns the status to go back to, if we were auto-away.
+    pub fn focus_gained(&mut self) -> Option<OwnStatus> {
+        self.unfocused_since = None;
+        self.input()
+    }
+
+    /// The user picked a status themselves, whatever it is, so it must not be overwritten when
+    /// they return.
+    pub fn status_changed(&mut self) {
+        self.restore = None;
+    }
+
+    /// Starts over on a new connection, which begins with the status we saved. Time spent
+    /// before it doesn't count as idle.
+    pub fn connected(&mut self) {
+        self.last_input = Instant::now();
+        self.restore = None;
+    }
+
+    /// Returns the away status to set, if we just went idle.
+    pub fn tick(&mut self, current: &OwnStatus) -> Option<OwnStatus> {
+        if self.restore.is_some() || !self.is_idle() {
+            return None;
+        }
+        // Busy and do not disturb say more than away does, leave them alone
+        if !matches!(
+            current.availability,
+            Availability::Online | Availability::Looking
+        ) {
+            return None;
+        }
+        self.restore = Some(current.clone());
+        Some(OwnStatus {
+            availability: Availability::Away,
+            message: self
+                .message
+                .clone()
+                .unwrap_or_else(|| current.message.clone()),
+        })
+    }
+
+    fn is_idle(&self) -> bool {
+        let idle = self
+            .idle_timeout
+            .is_some_and(|timeout| self.last_input.elapsed() >= timeout);
+        let unfocused = self
+            .unfocused_timeout
+            .zip(self.unfocused_since)
+            .is_some_and(|(timeout, since)| since.elapsed() >= timeout);
+        idle || unfocused
+    }
+}
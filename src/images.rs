@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use image::ImageReader;
+use ratatui_image::picker::{Picker, ProtocolType};
+use ratatui_image::protocol::StatefulProtocol;
+
+use crate::fetch::FetchRequest;
+use crate::storage;
+
+/// Avatars and eicons are small, anything bigger isn't one.
+const MAX_BYTES: u64 = 1024 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ImageKind {
+    Avatar,
+    Eicon,
+}
+
+/// An image the site hosts for a character or an eicon, by lowercased name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImageKey {
+    pub kind: ImageKind,
+    pub name: String,
+}
+
+impl ImageKey {
+    pub fn avatar(character: &str) -> Self {
+        ImageKey {
+            kind: ImageKind::Avatar,
+            name: character.to_lowercase(),
+        }
+    }
+
+    pub fn eicon(name: &str) -> Self {
+        ImageKey {
+            kind: ImageKind::Eicon,
+            name: name.to_lowercase(),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        match self.kind {
+            ImageKind::Avatar => {
+                format!("https://static.f-list.net/images/avatar/{}.png", self.name)
+            }
+            ImageKind::Eicon => format!("https://static.f-list.net/images/eicon/{}.gif", self.name),
+        }
+    }
+
+    fn path(&self, cache_dir: &Path) -> PathBuf {
+        let dir = match self.kind {
+            ImageKind::Avatar => "avatars",
+            ImageKind::Eicon => "eicons",
+        };
+        cache_dir.join(dir).join(storage::sanitize(&self.name))
+    }
+}
+
+enum Entry {
+    Fetching,
+    Ready(StatefulProtocol),
+    /// Doesn't exist or couldn't be decoded. Not asked for again until the next launch.
+    Failed,
+}
+
+/// Avatars and eicons, downloaded once into the cache directory and turned into whatever
+/// graphics the terminal supports.
+pub struct Images {
+    /// None when images are turned off for good, with `--no-images`.
+    picker: Option<Picker>,
+    /// Images can be hidden for a while without throwing away what was loaded.
+    pub shown: bool,
+    cache_dir: PathBuf,
+    entries: HashMap<ImageKey, Entry>,
+    /// Images asked for while drawing that have to be downloaded first.
+    missing: Vec<ImageKey>,
+}
+
+impl Images {
+    pub fn new(picker: Option<Picker>, cache_dir: PathBuf) -> Self {
+        Images {
+            shown: picker.is_some(),
+            picker,
+            cache_dir,
+            entries: HashMap::new(),
+            missing: Vec::new(),
+        }
+    }
+
+    /// Asks the terminal which graphics protocol it speaks. Has to happen before anything else
+    /// reads from it. Terminals that don't answer get half blocks.
+    pub fn picker() -> Picker {
+        Picker::from_query_stdio().unwrap_or_else(|_| {
+            let mut picker = Picker::from_fontsize((8, 16));
+            picker.set_protocol_type(ProtocolType::Halfblocks);
+            picker
+        })
+    }
+
+    pub fn toggle(&mut self) -> bool {
+        self.shown = self.picker.is_some() && !self.shown;
+        self.shown
+    }
+
+    /// The image, if it's loaded. Images that aren't are loaded from the cache, or downloaded
+    /// once `take_missing` is called.
+    pub fn get(&mut self, key: &ImageKey) -> Option<&mut StatefulProtocol> {
+        if !self.shown {
+            return None;
+        }
+        if !self.entries.contains_key(key) {
+            let path = key.path(&self.cache_dir);
+            let entry = if path.exists() {
+                self.load(&path)
+            } else {
+                self.missing.push(key.clone());
+                Entry::Fetching
+            };
+            self.entries.insert(key.clone(), entry);
+        }
+        match self.entries.get_mut(key) {
+            Some(Entry::Ready(protocol)) => Some(protocol),
+            _ => None,
+        }
+    }
+
+    /// Downloads for the images that aren't in the cache yet.
+    pub fn take_missing(&mut self) -> Vec<FetchRequest> {
+        let cache_dir = &self.cache_dir;
+        self.missing
+            .drain(..)
+            .map(|key| FetchRequest {
+                url: key.url(),
+                path: key.path(cache_dir),
+                max_bytes: MAX_BYTES,
+                images_only: true,
+            })
+            .collect()
+    }
+
+    /// A download couldn't be queued. The image is asked for again the next time it's drawn.
+    pub fn retry(&mut self, url: &str) {
+        self.entries
+            .retain(|key, entry| !(matches!(entry, Entry::Fetching) && key.url() == url));
+    }
+
+    /// A download finished, successfully or not. Returns whether it was one of ours.
+    pub fn fetched(&mut self, url: &str, result: Result<&Path, String>) -> bool {
+        let Some(key) = self
+            .entries
+            .iter()
+            .find(|(key, entry)| matches!(entry, Entry::Fetching) && key.url() == url)
+            .map(|(key, _)| key.clone())
+        else {
+            return false;
+        };
+        let entry = match result {
+            Ok(path) => self.load(path),
+            Err(_) => Entry::Failed,
+        };
+        self.entries.insert(key, entry);
+        true
+    }
+
+    fn load(&mut self, path: &Path) -> Entry {
+        match self.decode(path) {
+            Ok(protocol) => Entry::Ready(protocol),
+            Err(_) => Entry::Failed,
+        }
+    }
+
+    /// Turns any image file into something the terminal can show. Cached files have no
+    /// extension, so the format comes from their content.
+    pub fn decode(&mut self, path: &Path) -> Result<StatefulProtocol, String> {
+        let Some(picker) = &mut self.picker else {
+            return Err(String::from("Images are turned off with --no-images."));
+        };
+        let image = ImageReader::open(path)
+            .and_then(ImageReader::with_guessed_format)
+            .map_err(|error| error.to_string())?
+            .decode()
+            .map_err(|error| error.to_string())?;
+        Ok(picker.new_resize_protocol(image))
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+
+use crate::chat::LineKind;
+use crate::logs::{self, LogEntry};
+use crate::search::LogIndex;
+use crate::storage;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImportFormat {
+    /// F-Chat Horizon's binary logs: one file per conversation, named after it.
+    Horizon,
+    /// Text downloaded from the log viewer of the official web client.
+    Web,
+}
+
+/// The logs of one conversation, read from another client.
+pub struct Source {
+    /// `#channel` or `@character`.
+    pub conversation: String,
+    pub entries: Vec<LogEntry>,
+    /// Records or lines that couldn't be made sense of.
+    pub unreadable: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub conversation: String,
+    pub found: usize,
+    /// Entries that were already in our logs, or appeared more than once in the import.
+    pub duplicates: usize,
+    pub imported: usize,
+    pub unreadable: usize,
+}
+
+/// Reads a Horizon logs directory, or a single conversation file from one.
+pub fn read_horizon(path: &Path) -> io::Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for file in files(path)? {
+        // Horizon keeps an index next to every log, named after it with an extension
+        let Some(name) = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| !name.contains('.'))
+        else {
+            continue;
+        };
+        let conversation = if name.starts_with('#') {
+            name.to_owned()
+        } else {
+            format!("@{}", name)
+        };
+        let (entries, unreadable) = parse_horizon(&fs::read(&file)?);
+        sources.push(Source {
+            conversation,
+            entries,
+            unreadable,
+        });
+    }
+    Ok(sources)
+}
+
+/// Reads a web client export, or a directory of them, which all belong to `conversation`.
+pub fn read_web(path: &Path, conversation: &str) -> io::Result<Source> {
+    let mut source = Source {
+        conversation: conversation.to_owned(),
+        entries: Vec::new(),
+        unreadable: 0,
+    };
+    for file in files(path)? {
+        let (mut entries, unreadable) = parse_web(&String::from_utf8_lossy(&fs::read(&file)?));
+        source.entries.append(&mut entries);
+        source.unreadable += unreadable;
+    }
+    Ok(source)
+}
+
+fn files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Horizon records are: time (u32 seconds), type (u8), sender length (u8), sender, text
+/// length (u16), text and the record's own length (u16), all little endian.
+fn parse_horizon(data: &[u8]) -> (Vec<LogEntry>, usize) {
+    let mut entries = Vec::new();
+    let mut unreadable = 0;
+    let mut offset = 0;
+    while offset < data.len() {
+        let Some((size, entry)) = horizon_record(&data[offset..]) else {
+            // A truncated record, there's no telling where the next one would start
+            unreadable += 1;
+            break;
+        };
+        match entry {
+            Some(entry) => entries.push(entry),
+            None => unreadable += 1,
+        }
+        offset += size;
+    }
+    (entries, unreadable)
+}
+
+/// The size of the record at the start of `data`, and the entry in it if it could be read.
+fn horizon_record(data: &[u8]) -> Option<(usize, Option<LogEntry>)> {
+    let time = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?);
+    let kind = *data.get(4)?;
+    let sender_length = *data.get(5)? as usize;
+    let sender = data.get(6..6 + sender_length)?;
+    let text_start = 8 + sender_length;
+    let text_length =
+        u16::from_le_bytes(data.get(text_start - 2..text_start)?.try_into().ok()?) as usize;
+    let text = data.get(text_start..text_start + text_length)?;
+    let size = text_start + text_length + 2;
+    if data.len() < size {
+        return None;
+    }
+    let entry = (|| {
+        let sender = str::from_utf8(sender).ok()?;
+        Some(LogEntry {
+            timestamp: Local.timestamp_opt(time.into(), 0).single()?,
+            sender: (!sender.is_empty()).then(|| sender.to_owned()),
+            kind: match kind {
+                0 => LineKind::Message,
+                1 => LineKind::Emote,
+                2 => LineKind::Ad,
+                3 => LineKind::Roll,
+                // Warnings, events and broadcasts
+                _ => LineKind::System,
+            },
+            text: str::from_utf8(text).ok()?.to_owned(),
+        })
+    })();
+    Some((size, entry))
+}
+
+/// Web client exports have one message per line, `[YYYY-MM-DD HH:MM] Name: text`, with
+/// `*Name text` for emotes and no sender at all for events. Lines without a timestamp
+/// continue the message before them.
+fn parse_web(text: &str) -> (Vec<LogEntry>, usize) {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    let mut unreadable = 0;
+    for line in text.lines() {
+        if let Some(entry) = parse_web_line(line) {
+            entries.push(entry);
+        } else if let Some(last) = entries.last_mut() {
+            last.text.push('\n');
+            last.text.push_str(line);
+        } else if !line.trim().is_empty() {
+            unreadable += 1;
+        }
+    }
+    (entries, unreadable)
+}
+
+fn parse_web_line(line: &str) -> Option<LogEntry> {
+    let (time, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").ok()?;
+    let timestamp = Local.from_local_datetime(&time).earliest()?;
+    let (kind, sender, text) = if let Some(text) = rest.strip_prefix(' ') {
+        (LineKind::System, None, text)
+    } else if let Some(rest) = rest.strip_prefix('*') {
+        let (sender, text) = rest.split_once(' ')?;
+        (LineKind::Emote, Some(sender), text)
+    } else if let Some((sender, text)) = rest.split_once(": ") {
+        (LineKind::Message, Some(sender), text)
+    } else {
+        // Ads and rolls don't get a colon after the name
+        let (sender, text) = rest.split_once(' ')?;
+        (LineKind::Ad, Some(sender), text)
+    };
+    Some(LogEntry {
+        timestamp,
+        sender: sender.map(str::to_owned),
+        kind,
+        text: text.to_owned(),
+    })
+}
+
+/// Other clients only keep whole minutes or seconds, so entries are compared by minute.
+fn dedup_key(entry: &LogEntry) -> (i64, Option<String>, String) {
+    (
+        entry.timestamp.timestamp() / 60,
+        entry.sender.as_ref().map(|sender| sender.to_lowercase()),
+        entry.text.clone(),
+    )
+}
+
+/// Merges imported entries into the day files of a character's logs, leaving out the ones
+/// that are already there, and indexes the days that changed. With `dry_run` nothing is written.
+pub fn merge(
+    logs_dir: &Path,
+    index: &mut LogIndex,
+    source: Source,
+    dry_run: bool,
+) -> io::Result<ImportReport> {
+    let dir = conversation_dir(logs_dir, &source.conversation);
+    let mut report = ImportReport {
+        conversation: source.conversation,
+        found: source.entries.len(),
+        unreadable: source.unreadable,
+        ..ImportReport::default()
+    };
+    let mut days: BTreeMap<NaiveDate, Vec<LogEntry>> = BTreeMap::new();
+    for entry in source.entries {
+        days.entry(entry.timestamp.date_naive())
+            .or_default()
+            .push(entry);
+    }
+    for (date, entries) in days {
+        let path = dir.join(format!("{}.jsonl", date.format("%Y-%m-%d")));
+        let mut merged = if path.exists() {
+            logs::read_entries(&path)?
+        } else {
+            Vec::new()
+        };
+        let before = merged.len();
+        let mut seen: HashSet<_> = merged.iter().map(dedup_key).collect();
+        for entry in entries {
+            if seen.insert(dedup_key(&entry)) {
+                merged.push(entry);
+            } else {
+                report.duplicates += 1;
+            }
+        }
+        report.imported += merged.len() - before;
+        if !dry_run && merged.len() > before {
+            merged.sort_by_key(|entry| entry.timestamp);
+            logs::write_entries(&path, &merged)?;
+            index.rewritten(&path, &merged)?;
+        }
+    }
+    Ok(report)
+}
+
+/// Other clients don't always keep the case of names, so reuse a directory that only differs in
+/// case instead of splitting the conversation in two.
+fn conversation_dir(logs_dir: &Path, conversation: &str) -> PathBuf {
+    let name = storage::sanitize(conversation);
+    fs::read_dir(logs_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(&name)
+        })
+        .map_or_else(|| logs_dir.join(&name), |entry| entry.path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn horizon(time: u32, kind: u8, sender: &[u8], text: &[u8]) -> Vec<u8> {
+        let mut record = time.to_le_bytes().to_vec();
+        record.push(kind);
+        record.push(sender.len() as u8);
+        record.extend_from_slice(sender);
+        record.extend_from_slice(&(text.len() as u16).to_le_bytes());
+        record.extend_from_slice(text);
+        let size = record.len() as u16;
+        record.extend_from_slice(&size.to_le_bytes());
+        record
+    }
+
+    fn entry(minute: i64, sender: &str, text: &str) -> LogEntry {
+        LogEntry {
+            timestamp: Local.timestamp_opt(1_700_000_000 + minute * 60, 0).unwrap(),
+            sender: Some(sender.to_owned()),
+            kind: LineKind::Message,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn truncated_horizon_record_is_unreadable() {
+        let mut data = horizon(1_700_000_000, 0, b"Alice", b"hello");
+        let second = horizon(1_700_000_060, 1, b"Bob", b"waves");
+        data.extend_from_slice(&second[..second.len() - 4]);
+        let (entries, unreadable) = parse_horizon(&data);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sender.as_deref(), Some("Alice"));
+        assert_eq!(entries[0].text, "hello");
+        assert_eq!(unreadable, 1);
+    }
+
+    #[test]
+    fn bad_utf8_horizon_record_is_skipped() {
+        let mut data = horizon(1_700_000_000, 0, b"Alice", b"caf\xe9");
+        data.extend(horizon(1_700_000_060, 1, b"Bob", b"waves"));
+        let (entries, unreadable) = parse_horizon(&data);
+        assert_eq!(unreadable, 1);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sender.as_deref(), Some("Bob"));
+        assert_eq!(entries[0].kind, LineKind::Emote);
+    }
+
+    #[test]
+    fn merge_treats_sender_case_as_the_same() {
+        let source = Source {
+            conversation: String::from("@Alice"),
+            entries: vec![
+                entry(0, "Alice", "hello"),
+                entry(0, "alice", "hello"),
+                entry(1, "Alice", "bye"),
+            ],
+            unreadable: 0,
+        };
+        let logs_dir = std::env::temp_dir().join("rsfchat-import-test-missing");
+        let mut index = LogIndex::open(logs_dir.clone());
+        let report = merge(&logs_dir, &mut index, source, true).unwrap();
+        assert_eq!(report.found, 3);
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.imported, 2);
+    }
+}
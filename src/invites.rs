@@ -0,0 +1,71 @@
+use crokey::{KeyCombination, key};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, List, ListState, Paragraph},
+};
+
+/// An invite to a room, from CIU, waiting for an answer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invite {
+    pub sender: String,
+    pub title: String,
+    pub channel: String,
+}
+
+pub enum InviteAction {
+    Close,
+    /// Joins the room and drops the invite.
+    Accept(Invite),
+    /// Drops the invite, without telling anyone.
+    Decline(Invite),
+}
+
+/// Lists the invites that came in. Anyone can send one, so only y accepts: a keypress meant
+/// for the composer, like Enter, never joins a room.
+pub struct InviteList {
+    list_state: ListState,
+}
+
+impl InviteList {
+    pub fn new() -> Self {
+        InviteList {
+            list_state: ListState::default().with_selected(Some(0)),
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination, invites: &[Invite]) -> Option<InviteAction> {
+        match key {
+            key!(esc) => return Some(InviteAction::Close),
+            key!(y) => {
+                let invite = invites.get(self.list_state.selected()?)?;
+                return Some(InviteAction::Accept(invite.clone()));
+            }
+            key!(n) => {
+                let invite = invites.get(self.list_state.selected()?)?;
+                return Some(InviteAction::Decline(invite.clone()));
+            }
+            key!(up) => self.list_state.select_previous(),
+            key!(down) => self.list_state.select_next(),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect, invites: &[Invite]) {
+        let block = Block::bordered().title("Invites (y: join, n: decline, Esc: later)");
+        frame.render_widget(Clear, area);
+        if invites.is_empty() {
+            frame.render_widget(Paragraph::new("No invites.").block(block), area);
+            return;
+        }
+        let items = invites
+            .iter()
+            .map(|invite| format!("{} invited you to {}", invite.sender, invite.title));
+        frame.render_stateful_widget(
+            List::new(items).highlight_symbol("> ").block(block),
+            area,
+            &mut self.list_state,
+        );
+    }
+}
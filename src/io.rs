@@ -1,3 +1,4 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use fchat::{self, ClientMessage, Server, ServerMessage, Ticket};
@@ -5,11 +6,17 @@ use fchat::{self, ClientMessage, Server, ServerMessage, Ticket};
 use futures::{StreamExt, prelude::*};
 
 use miette::IntoDiagnostic;
+use reqwest::redirect::Policy;
 use stream::TryStreamExt;
-use tokio::sync::mpsc::{Sender, channel, unbounded_channel};
+use tokio::sync::Semaphore;
+use tokio::sync::mpsc::{Sender, UnboundedSender, channel, unbounded_channel};
 use tokio::time::interval;
 
 use crate::app::{AppError, AppEvent, EventStream};
+use crate::fetch::{self, FetchRequest};
+use crate::outgoing::{self, Outgoing};
+use crate::preview::Blocklist;
+use crate::report::{self, Report};
 
 pub struct ChatController {
     sender: Sender<IoRequest>,
@@ -24,6 +31,21 @@ impl ChatController {
         self.send(IoRequest::Connect { ticket, character });
     }
 
+    /// Uploads a report's log, reported with `AppEvent::ReportUploaded`.
+    pub fn upload_report(&self, ticket: Ticket, reporter: String, report: Report) {
+        self.send(IoRequest::UploadReport {
+            ticket,
+            reporter,
+            report,
+        });
+    }
+
+    /// Queues a download. Returns false when too many are queued already, which drawing must
+    /// never wait for.
+    pub fn fetch(&self, request: FetchRequest) -> bool {
+        self.sender.try_send(IoRequest::Fetch(request)).is_ok()
+    }
+
     fn send(&self, request: IoRequest) {
         self.sender
             .blocking_send(request)
@@ -34,11 +56,52 @@ impl ChatController {
 pub type RequestSender = Sender<IoRequest>;
 
 pub enum IoRequest {
-    GetTicket { username: String, password: String },
-    Connect { ticket: Ticket, character: String },
+    GetTicket {
+        username: String,
+        password: String,
+    },
+    Connect {
+        ticket: Ticket,
+        character: String,
+    },
+    /// Downloads into the cache, reported with `AppEvent::Fetched`.
+    Fetch(FetchRequest),
+    UploadReport {
+        ticket: Ticket,
+        reporter: String,
+        report: Report,
+    },
+}
+
+/// Downloads running at once, the rest wait their turn.
+const MAX_DOWNLOADS: usize = 4;
+
+/// Redirects followed before a download is given up on.
+const MAX_REDIRECTS: usize = 10;
+
+/// Downloads only ever come from links posted in chat, so a redirect must not lead somewhere
+/// the blocklist would have refused to go.
+fn http_client(blocklist: Blocklist) -> reqwest::Client {
+    let policy = Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("Too many redirects")
+        } else if blocklist.blocks(attempt.url().as_str()) {
+            let error = format!(
+                "Redirected to a blocked address: {}",
+                attempt.url().as_str()
+            );
+            attempt.error(error)
+        } else {
+            attempt.follow()
+        }
+    });
+    reqwest::Client::builder()
+        .redirect(policy)
+        .build()
+        .expect("Couldn't set up the HTTP client")
 }
 
-pub fn start() -> miette::Result<(ChatController, EventStream)> {
+pub fn start(blocklist: Blocklist) -> miette::Result<(ChatController, EventStream)> {
     let (event_sender, event_receiver) = unbounded_channel();
     let (request_sender, mut request_receiver) = channel(16);
     std::thread::Builder::new()
@@ -51,47 +114,100 @@ pub fn start() -> miette::Result<(ChatController, EventStream)> {
                     tokio::spawn(async move {
                         let mut event_stream = crossterm::event::EventStream::new();
                         while let Some(event) = event_stream.next().await {
-                            event_sender.send(AppEvent::Crossterm(event)).unwrap();
+                            let Ok(()) = event_sender.send(AppEvent::Crossterm(event)) else {
+                                return;
+                            };
+                        }
+                    });
+                }
+                {
+                    let event_sender = event_sender.clone();
+                    tokio::spawn(async move {
+                        let mut interval = interval(Duration::from_secs(1));
+                        loop {
+                            interval.tick().await;
+                            let Ok(()) = event_sender.send(AppEvent::Tick) else {
+                                return;
+                            };
                         }
                     });
                 }
 
+                let client = http_client(blocklist);
+                let downloads = Arc::new(Semaphore::new(MAX_DOWNLOADS));
                 while let Some(request) = request_receiver.recv().await {
                     match request {
+                        IoRequest::Fetch(request) => {
+                            let client = client.clone();
+                            let event_sender = event_sender.clone();
+                            let downloads = downloads.clone();
+                            tokio::spawn(async move {
+                                let Ok(_permit) = downloads.acquire().await else {
+                                    return;
+                                };
+                                let result = fetch::download(&client, &request).await;
+                                let _ = event_sender.send(AppEvent::Fetched {
+                                    url: request.url,
+                                    result,
+                                });
+                            });
+                        }
+                        IoRequest::UploadReport {
+                            ticket,
+                            reporter,
+                            report,
+                        } => {
+                            let client = client.clone();
+                            let event_sender = event_sender.clone();
+                            tokio::spawn(async move {
+                                let result =
+                                    report::upload_log(&client, &ticket, &reporter, &report).await;
+                                let _ =
+                                    event_sender.send(AppEvent::ReportUploaded { report, result });
+                            });
+                        }
                         IoRequest::GetTicket { username, password } => {
                             let ticket = Ticket::request(&username, &password).await;
-                            event_sender.send(AppEvent::Ticket(ticket)).unwrap();
+                            let _ = event_sender.send(AppEvent::Ticket(ticket));
                         }
                         IoRequest::Connect { ticket, character } => {
-                            match connect(None, ticket, character).await {
+                            match connect(None, ticket, character, event_sender.clone()).await {
                                 Ok((sender, mut stream)) => {
-                                    event_sender.send(AppEvent::Connected(sender)).unwrap();
+                                    let _ = event_sender.send(AppEvent::Connected(sender));
                                     let event_sender = event_sender.clone();
                                     tokio::spawn(async move {
                                         loop {
+                                            // The app going away is the only way a send fails,
+                                            // and then there's nobody left to read for
                                             match stream.try_next().await {
                                                 Ok(None) => {
-                                                    // TODO: Figure out if I need to do anything here
+                                                    let _ = event_sender.send(
+                                                        AppEvent::Disconnected(String::from(
+                                                            "The server closed the connection",
+                                                        )),
+                                                    );
                                                     break;
                                                 }
                                                 Ok(Some(message)) => {
-                                                    event_sender
-                                                        .send(AppEvent::Chat(message))
-                                                        .unwrap();
+                                                    let Ok(()) =
+                                                        event_sender.send(AppEvent::Chat(message))
+                                                    else {
+                                                        break;
+                                                    };
                                                 }
                                                 Err(error) => {
-                                                    event_sender
-                                                        .send(AppEvent::Error(
-                                                            AppError::Connection(error),
-                                                        ))
-                                                        .unwrap();
+                                                    let _ = event_sender.send(AppEvent::Error(
+                                                        AppError::Connection(error),
+                                                    ));
+                                                    break;
                                                 }
                                             }
                                         }
                                     });
                                 }
                                 Err(error) => {
-                                    event_sender.send(AppEvent::Error(AppError::Connection(error)));
+                                    let _ = event_sender
+                                        .send(AppEvent::Error(AppError::Connection(error)));
                                 }
                             }
                         }
@@ -110,9 +226,10 @@ async fn connect(
     server: Option<Server>,
     ticket: Ticket,
     character: String,
+    event_sender: UnboundedSender<AppEvent>,
 ) -> Result<
     (
-        tokio::sync::mpsc::Sender<ClientMessage>,
+        tokio::sync::mpsc::Sender<Outgoing>,
         impl Stream<Item = Result<ServerMessage, fchat::Error>>,
     ),
     fchat::Error,
@@ -127,20 +244,20 @@ async fn connect(
             env!("CARGO_PKG_VERSION").to_string(),
         )
         .await?;
-    let (mut sink, stream) = connection.split();
+    let (sink, stream) = connection.split();
     // The sink isn't cloneable, but channel senders are
-    let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+    let (tx, rx) = tokio::sync::mpsc::channel(32);
     let tx2 = tx.clone();
-    tokio::spawn(async move {
-        while let Some(message) = rx.recv().await {
-            sink.send(message).await.unwrap();
-        }
-    });
+    tokio::spawn(outgoing::run(sink, rx, event_sender));
     tokio::spawn(async move {
         let mut interval = interval(Duration::from_secs(30));
         loop {
             interval.tick().await;
-            let Ok(()) = tx.send(ClientMessage::PIN).await else {
+            let ping = Outgoing::Message {
+                id: None,
+                message: ClientMessage::PIN,
+            };
+            let Ok(()) = tx.send(ping).await else {
                 return;
             };
         }
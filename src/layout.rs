@@ -0,0 +1,204 @@
+use ratatui::layout::Rect;
+use ratatui_macros::{horizontal, vertical};
+use serde::{Deserialize, Serialize};
+
+use crate::widgets::Description;
+
+/// Narrower than this, the scrollback doesn't give up columns to the member list.
+pub const MIN_SCROLLBACK_WIDTH: u16 = 40;
+/// Shorter than this, the scrollback doesn't give up rows to the header and description.
+const MIN_SCROLLBACK_HEIGHT: u16 = 5;
+/// One row to type in, one for the counts under it.
+const MIN_COMPOSER_HEIGHT: u16 = 2;
+const MAX_COMPOSER_HEIGHT: u16 = 20;
+const MIN_MEMBERS_WIDTH: u16 = 12;
+const MAX_MEMBERS_WIDTH: u16 = 60;
+
+/// Which side of the scrollback the member list is on.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Left,
+    #[default]
+    Right,
+}
+
+/// Where the tab bar is.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    #[default]
+    Top,
+    Bottom,
+}
+
+/// How the screen is split into panes. Remembered between launches.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub tabs: Edge,
+    pub members: Side,
+    pub show_members: bool,
+    pub members_width: u16,
+    pub composer_height: u16,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            tabs: Edge::Top,
+            members: Side::Right,
+            show_members: true,
+            members_width: 24,
+            composer_height: 5,
+        }
+    }
+}
+
+/// Moves one of the splits between panes, from the keyboard.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Split {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Where the panes of the chat screen were last drawn. Collapsed panes are empty.
+#[derive(Default, Clone, Copy)]
+pub struct ChatAreas {
+    pub tabs: Rect,
+    pub header: Rect,
+    pub description: Rect,
+    pub scrollback: Rect,
+    pub members: Rect,
+    pub composer: Rect,
+}
+
+impl Layout {
+    /// Splits up the chat screen. The member list is only wanted for channels. Panes that
+    /// don't fit collapse: the member list first, then the header and description.
+    pub fn chat(&self, area: Rect, description: &Description, members: bool) -> ChatAreas {
+        let composer_height = self
+            .composer_height
+            .min(area.height.saturating_sub(1 + MIN_SCROLLBACK_HEIGHT))
+            .max(MIN_COMPOSER_HEIGHT);
+        let roomy = area.height >= 3 + composer_height + MIN_SCROLLBACK_HEIGHT;
+        let header_height = u16::from(roomy);
+        let description_height = if roomy {
+            description.height(area.width, area.height / 3)
+        } else {
+            0
+        };
+        let mut areas = ChatAreas::default();
+        let middle;
+        match self.tabs {
+            Edge::Top => {
+                [
+                    areas.tabs,
+                    areas.header,
+                    areas.description,
+                    middle,
+                    areas.composer,
+                ] = vertical![==1, ==header_height, ==description_height, *=1, ==composer_height]
+                    .areas(area);
+            }
+            Edge::Bottom => {
+                [
+                    areas.header,
+                    areas.description,
+                    middle,
+                    areas.composer,
+                    areas.tabs,
+                ] = vertical![==header_height, ==description_height, *=1, ==composer_height, ==1]
+                    .areas(area);
+            }
+        }
+        let members_width = self.members_width;
+        if !members || !self.show_members || area.width < members_width + MIN_SCROLLBACK_WIDTH {
+            areas.scrollback = middle;
+            return areas;
+        }
+        match self.members {
+            Side::Left => {
+                [areas.members, areas.scrollback] = horizontal![==members_width, *=1].areas(middle);
+            }
+            Side::Right => {
+                [areas.scrollback, areas.members] = horizontal![*=1, ==members_width].areas(middle);
+            }
+        }
+        areas
+    }
+
+    /// Moves a split by one row or column. Returns whether anything changed.
+    pub fn resize(&mut self, split: Split) -> bool {
+        let before = self.clone();
+        // The member list grows when the split moves away from it
+        let wider = match self.members {
+            Side::Left => Split::Right,
+            Side::Right => Split::Left,
+        };
+        match split {
+            Split::Left | Split::Right if self.show_members => {
+                self.members_width = if split == wider {
+                    self.members_width + 1
+                } else {
+                    self.members_width.saturating_sub(1)
+                }
+                .clamp(MIN_MEMBERS_WIDTH, MAX_MEMBERS_WIDTH);
+            }
+            Split::Left | Split::Right => {}
+            Split::Up => self.composer_height = (self.composer_height + 1).min(MAX_COMPOSER_HEIGHT),
+            Split::Down => {
+                self.composer_height = self
+                    .composer_height
+                    .saturating_sub(1)
+                    .max(MIN_COMPOSER_HEIGHT);
+            }
+        }
+        *self != before
+    }
+
+    /// Changes the arrangement from `/layout`, returning what it's like now.
+    pub fn arrange(&mut self, argument: &str) -> Result<String, String> {
+        let mut words = argument.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("members"), Some("left")) => {
+                self.members = Side::Left;
+                self.show_members = true;
+            }
+            (Some("members"), Some("right")) => {
+                self.members = Side::Right;
+                self.show_members = true;
+            }
+            (Some("members"), Some("hide")) => self.show_members = false,
+            (Some("members"), Some("show")) => self.show_members = true,
+            (Some("tabs"), Some("top")) => self.tabs = Edge::Top,
+            (Some("tabs"), Some("bottom")) => self.tabs = Edge::Bottom,
+            (Some("reset"), None) => *self = Layout::default(),
+            (None, _) => {}
+            _ => {
+                return Err(String::from(
+                    "Usage: /layout members left|right|hide|show, /layout tabs top|bottom or /layout reset",
+                ));
+            }
+        }
+        Ok(self.describe())
+    }
+
+    fn describe(&self) -> String {
+        let members = match (self.show_members, self.members) {
+            (false, _) => String::from("hidden"),
+            (true, Side::Left) => format!("on the left, {} columns", self.members_width),
+            (true, Side::Right) => format!("on the right, {} columns", self.members_width),
+        };
+        let tabs = match self.tabs {
+            Edge::Top => "top",
+            Edge::Bottom => "bottom",
+        };
+        format!(
+            "Tabs at the {}, member list {}, composer {} rows. Alt-arrows move the splits.",
+            tabs, members, self.composer_height
+        )
+    }
+}
@@ -0,0 +1,104 @@
+use std::process::{Command, Stdio};
+
+use crokey::{KeyCombination, key};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, List, ListState, Paragraph},
+};
+
+use crate::bbcode::{self, Link};
+use crate::chat::ChatLine;
+
+/// Opens a web address with `browser`, a shell command that gets it in `RSFCHAT_URL`. Without
+/// one, the system's default browser is used. The address is never parsed by a shell.
+pub fn open(url: &str, browser: Option<&str>) -> std::io::Result<()> {
+    let mut command = match browser {
+        Some(browser) => {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(browser);
+            command
+        }
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        // `cmd /c start` would run anything after a `&` in the address
+        None if cfg!(target_os = "windows") => {
+            let mut command = Command::new("rundll32");
+            command.arg("url.dll,FileProtocolHandler");
+            command
+        }
+        None => Command::new("xdg-open"),
+    };
+    if browser.is_none() {
+        command.arg(url);
+    }
+    command
+        .env("RSFCHAT_URL", url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+}
+
+pub enum PickerAction {
+    Cancel,
+    Open(Link),
+}
+
+/// Lists the links in the messages on screen, newest first, to open one from the keyboard.
+pub struct LinkPicker {
+    links: Vec<Link>,
+    list_state: ListState,
+}
+
+impl LinkPicker {
+    pub fn new<'a>(lines: impl Iterator<Item = &'a ChatLine>) -> Self {
+        let all: Vec<Link> = lines.flat_map(|line| bbcode::links(&line.text)).collect();
+        let mut links: Vec<Link> = Vec::new();
+        for link in all.into_iter().rev() {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+        LinkPicker {
+            links,
+            list_state: ListState::default().with_selected(Some(0)),
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination) -> Option<PickerAction> {
+        match key {
+            key!(esc) => return Some(PickerAction::Cancel),
+            key!(enter) => {
+                let index = self.list_state.selected()?;
+                return Some(PickerAction::Open(self.links.get(index)?.clone()));
+            }
+            key!(up) | key!(k) => self.list_state.select_previous(),
+            key!(down) | key!(j) => self.list_state.select_next(),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title("Links (Enter: open, Esc: close)");
+        frame.render_widget(Clear, area);
+        if self.links.is_empty() {
+            frame.render_widget(
+                Paragraph::new("No links in the messages on screen.").block(block),
+                area,
+            );
+            return;
+        }
+        let items = self.links.iter().map(|link| match link {
+            Link::Url(url) => url.clone(),
+            Link::User(character) => format!("Profile of {}", character),
+            Link::Session { title, .. } => format!("Join #{}", title),
+        });
+        frame.render_stateful_widget(
+            List::new(items).highlight_symbol("> ").block(block),
+            area,
+            &mut self.list_state,
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::bbcode;
+use crate::chat::{ChatLine, LineKind, Target};
+use crate::search::{LogIndex, SearchQuery, SearchResult};
+use crate::storage;
+
+/// One line of a conversation log, as stored on disk.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub timestamp: DateTime<Local>,
+    pub sender: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: LineKind,
+    /// The message exactly as it was sent, BBCode included.
+    pub text: String,
+}
+
+impl From<LogEntry> for ChatLine {
+    fn from(entry: LogEntry) -> Self {
+        ChatLine {
+            timestamp: entry.timestamp,
+            kind: entry.kind,
+            sender: entry.sender,
+            text: entry.text,
+            mention: false,
+            pending: None,
+        }
+    }
+}
+
+impl From<&ChatLine> for LogEntry {
+    fn from(line: &ChatLine) -> Self {
+        LogEntry {
+            timestamp: line.timestamp,
+            sender: line.sender.clone(),
+            kind: line.kind,
+            text: line.text.clone(),
+        }
+    }
+}
+
+/// Name of the directory holding a conversation's logs: `#channel` or `@character`.
+/// The console isn't a conversation and doesn't get logged.
+pub fn conversation_name(target: &Target) -> Option<String> {
+    match target {
+        Target::Console => None,
+        Target::Channel(channel) => Some(format!("#{}", channel)),
+        Target::Private(character) => Some(format!("@{}", character)),
+    }
+}
+
+pub fn conversation_target(name: &str) -> Option<Target> {
+    if let Some(channel) = name.strip_prefix('#') {
+        Some(Target::Channel(channel.to_owned()))
+    } else {
+        name.strip_prefix('@')
+            .map(|character| Target::Private(character.to_owned()))
+    }
+}
+
+pub fn logs_dir(data_dir: &Path, character: &str) -> PathBuf {
+    storage::character_dir(data_dir, character).join("logs")
+}
+
+/// Appends displayed lines to one JSON Lines file per conversation per day, and keeps the
+/// search index up to date with them.
+pub struct ChatLog {
+    dir: PathBuf,
+    /// Loaded the first time it's needed, which a log only opened to read a day never does.
+    index: RefCell<Option<LogIndex>>,
+}
+
+impl ChatLog {
+    pub fn new(data_dir: &Path, character: &str) -> Self {
+        ChatLog {
+            dir: logs_dir(data_dir, character),
+            index: RefCell::new(None),
+        }
+    }
+
+    fn with_index<T>(&self, f: impl FnOnce(&mut LogIndex) -> T) -> T {
+        let mut index = self.index.borrow_mut();
+        f(index.get_or_insert_with(|| LogIndex::open(self.dir.clone())))
+    }
+
+    pub fn search(&self, query: &SearchQuery, limit: usize) -> io::Result<Vec<SearchResult>> {
+        self.with_index(|index| index.search(query, limit))
+    }
+
+    pub fn append(&self, target: &Target, line: &ChatLine) -> io::Result<()> {
+        let Some(name) = conversation_name(target) else {
+            return Ok(());
+        };
+        let dir = self.dir.join(storage::sanitize(&name));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.jsonl", line.timestamp.format("%Y-%m-%d")));
+        let entry = LogEntry::from(line);
+        let mut data = serde_json::to_vec(&entry)?;
+        data.push(b'\n');
+        // A single write of the whole line, synced before we move on, so a crash can at worst
+        // lose the last line instead of leaving a torn one in the middle of the file
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&data)?;
+        file.sync_data()?;
+        let size = file.metadata()?.len();
+        self.with_index(|index| {
+            index.appended(&storage::sanitize(&name), &entry, size, data.len() as u64)
+        });
+        Ok(())
+    }
+
+    /// The last `count` logged lines of a conversation, oldest first.
+    pub fn recent(&self, target: &Target, count: usize) -> io::Result<Vec<LogEntry>> {
+        let Some(name) = conversation_name(target) else {
+            return Ok(Vec::new());
+        };
+        let dir = self.dir.join(storage::sanitize(&name));
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        // Walk back one day at a time until we have enough
+        for (_, path) in log_files(&dir)?.into_iter().rev() {
+            let mut day = read_entries(&path)?;
+            day.append(&mut entries);
+            entries = day;
+            if entries.len() >= count {
+                break;
+            }
+        }
+        let skip = entries.len().saturating_sub(count);
+        Ok(entries.split_off(skip))
+    }
+
+    /// Everything logged in a conversation on a given day.
+    pub fn day(&self, target: &Target, date: NaiveDate) -> io::Result<Vec<LogEntry>> {
+        let Some(name) = conversation_name(target) else {
+            return Ok(Vec::new());
+        };
+        let path = self
+            .dir
+            .join(storage::sanitize(&name))
+            .join(format!("{}.jsonl", date.format("%Y-%m-%d")));
+        read_entries(&path)
+    }
+
+    /// Deletes the log files of every conversation that are older than `days` days.
+    pub fn prune(&self, days: u64) -> io::Result<()> {
+        let cutoff = Local::now().date_naive() - chrono::Days::new(days);
+        let Ok(conversations) = fs::read_dir(&self.dir) else {
+            return Ok(());
+        };
+        for conversation in conversations {
+            for (date, path) in log_files(&conversation?.path())? {
+                if date < cutoff {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ChatLog {
+    fn drop(&mut self) {
+        // Whatever wasn't saved is indexed again by the next search, so a failure costs nothing
+        if let Some(index) = self.index.get_mut() {
+            let _ = index.save();
+        }
+    }
+}
+
+/// The daily log files in a conversation directory, oldest first.
+pub fn log_files(dir: &Path) -> io::Result<Vec<(NaiveDate, PathBuf)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != "jsonl")
+        {
+            continue;
+        }
+        let date = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| NaiveDate::parse_from_str(stem, "%Y-%m-%d").ok());
+        if let Some(date) = date {
+            files.push((date, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Reads a log file, skipping lines that can't be parsed instead of failing the whole file.
+pub fn read_entries(path: &Path) -> io::Result<Vec<LogEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        if let Ok(entry) = serde_json::from_str(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Replaces a log file, through a temporary file so a crash never leaves half of it behind.
+pub fn write_entries(path: &Path, entries: &[LogEntry]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut data = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut data, entry)?;
+        data.push(b'\n');
+    }
+    let temporary = path.with_extension("tmp");
+    // Synced first, so the rename can never reach the disk before the data does
+    let mut file = File::create(&temporary)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+    fs::rename(temporary, path)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Text,
+    Html,
+}
+
+/// Writes a conversation's logs between two dates (inclusive) as plain text or HTML.
+pub fn export(
+    dir: &Path,
+    format: ExportFormat,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    if format == ExportFormat::Html {
+        writeln!(
+            output,
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title></head>\n<body>",
+            html_escape(&dir.file_name().unwrap_or_default().to_string_lossy())
+        )?;
+    }
+    for (date, path) in log_files(dir)? {
+        if from.is_some_and(|from| date < from) || to.is_some_and(|to| date > to) {
+            continue;
+        }
+        for entry in read_entries(&path)? {
+            let timestamp = entry.timestamp.format("%Y-%m-%d %H:%M:%S");
+            let sender = entry.sender.as_deref().unwrap_or_default();
+            match format {
+                ExportFormat::Text => {
+                    let text = bbcode::strip(&entry.text);
+                    let line = match entry.kind {
+                        LineKind::Message => format!("<{}> {}", sender, text),
+                        LineKind::Emote => format!("* {} {}", sender, text),
+                        LineKind::Ad => format!("[AD] <{}> {}", sender, text),
+                        LineKind::System => format!("*** {}", text),
+                        LineKind::Roll => format!("🎲 {} {}", sender, text),
+                    };
+                    writeln!(output, "[{}] {}", timestamp, line)?;
+                }
+                ExportFormat::Html => {
+                    let sender = html_escape(sender);
+                    let text = bbcode::to_html(&entry.text);
+                    let line = match entry.kind {
+                        LineKind::Message => format!("&lt;{}&gt; {}", sender, text),
+                        LineKind::Emote => format!("* {} {}", sender, text),
+                        LineKind::Ad => format!("[AD] &lt;{}&gt; {}", sender, text),
+                        LineKind::System => format!("*** {}", text),
+                        LineKind::Roll => format!("🎲 <b>{}</b> {}", sender, text),
+                    };
+                    writeln!(output, "<p>[{}] {}</p>", timestamp, line)?;
+                }
+            }
+        }
+    }
+    if format == ExportFormat::Html {
+        writeln!(output, "</body>\n</html>")?;
+    }
+    Ok(())
+}
+
+pub fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("<br>"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,26 +1,88 @@
 use app::App;
+use clap::Parser;
+use config::Config;
+use crossterm::event::{
+    DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture,
+};
+use crossterm::execute;
+use images::Images;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::error::TryRecvError;
 
+mod ads;
 mod app;
+mod bbcode;
+mod chat;
+mod cli;
+mod clipboard;
+mod command;
+mod config;
+mod confirm;
+mod console;
+mod context;
+mod description;
+mod dice;
+mod fetch;
+mod idle;
+mod images;
+mod import;
+mod invites;
 mod io;
+mod layout;
+mod links;
+mod logs;
+mod mention;
+mod moderation;
+mod notify;
+mod outgoing;
+mod preview;
+mod report;
+mod rooms;
+mod search;
+mod staff;
+mod status;
+mod storage;
+mod typing;
 mod widgets;
 
 fn main() {
     std::panic::set_hook(Box::new(|panic_info| {
         better_panic::Settings::auto().create_panic_handler()(panic_info);
     }));
+    let config = Config::parse();
+    if let Some(command) = &config.command {
+        if let Err(error) = cli::run(command, &config) {
+            eprintln!("{:?}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
     let terminal = ratatui::init();
-    let run_result = run(terminal);
+    // Focus events drive auto-away, terminals that don't support them just never send any
+    let _ = execute!(std::io::stdout(), EnableFocusChange);
+    if config.mouse {
+        let _ = execute!(std::io::stdout(), EnableMouseCapture);
+    }
+    // The terminal has to be asked about graphics before anything else starts reading from it
+    let picker = (!config.no_images).then(Images::picker);
+    let run_result = run(terminal, config, picker);
+    // It may have been turned on with /mouse since
+    let _ = execute!(std::io::stdout(), DisableFocusChange, DisableMouseCapture);
     ratatui::restore();
     if let Err(error) = run_result {
         eprintln!("{:?}", error);
+        std::process::exit(1);
     }
 }
 
-fn run(mut terminal: ratatui::DefaultTerminal) -> miette::Result<()> {
-    let (connection, mut event_stream) = io::start()?;
-    let mut app = App::new(connection);
+fn run(
+    mut terminal: ratatui::DefaultTerminal,
+    config: Config,
+    picker: Option<ratatui_image::picker::Picker>,
+) -> miette::Result<()> {
+    let blocklist = preview::Blocklist::new(&config.preview_blocklist);
+    let (connection, mut event_stream) = io::start(blocklist)?;
+    let mut app = App::new(connection, config, picker);
     while !app.should_quit {
         app.draw(&mut terminal).unwrap();
         let timeout = Instant::now() + Duration::from_millis(500);
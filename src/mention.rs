@@ -0,0 +1,69 @@
+/// Case-insensitive matcher for the connected character's name and its aliases.
+pub struct MentionMatcher {
+    names: Vec<String>,
+}
+
+impl MentionMatcher {
+    pub fn new(character: &str, aliases: &[String]) -> Self {
+        let names = std::iter::once(character)
+            .chain(aliases.iter().map(String::as_str))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        MentionMatcher { names }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        if self.names.is_empty() {
+            return false;
+        }
+        let text = text.to_lowercase();
+        self.names.iter().any(|name| contains_word(&text, name))
+    }
+}
+
+/// Only count whole-word hits, so "Ann" doesn't match "Announcement".
+fn contains_word(haystack: &str, needle: &str) -> bool {
+    haystack.match_indices(needle).any(|(start, _)| {
+        let before = haystack[..start].chars().next_back();
+        let after = haystack[start + needle.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_whole_words_only() {
+        let matcher = MentionMatcher::new("Ann", &[]);
+        assert!(matcher.matches("Ann, are you there?"));
+        assert!(matcher.matches("hi ann"));
+        assert!(matcher.matches("(Ann)"));
+        assert!(!matcher.matches("Announcement!"));
+        assert!(!matcher.matches("Joanne"));
+        assert!(!matcher.matches("Ann_Other"));
+        // A hit inside a word doesn't hide a real one later on
+        assert!(matcher.matches("Annie said hi to Ann"));
+    }
+
+    #[test]
+    fn ignores_case_in_names_and_text() {
+        let matcher = MentionMatcher::new("Lady Ann", &[String::from("ÉLODIE")]);
+        assert!(matcher.matches("LADY ANN waves"));
+        assert!(matcher.matches("bonjour élodie"));
+        assert!(!matcher.matches("lady anne"));
+    }
+
+    #[test]
+    fn blank_aliases_match_nothing() {
+        let matcher = MentionMatcher::new("", &[String::from("  ")]);
+        assert!(!matcher.matches("anything at all"));
+    }
+}
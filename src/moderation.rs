@@ -0,0 +1,254 @@
+use crokey::{KeyCombination, key};
+use fchat::{ChannelMode, ClientMessage};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, List, ListState},
+};
+
+/// What a channel operator can do, each with the slash command that does it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModAction {
+    Kick,
+    Ban,
+    Unban,
+    Timeout,
+    Invite,
+    BanList,
+    Description,
+    Mode,
+    Op,
+    Deop,
+    SetOwner,
+    Open,
+    Close,
+}
+
+impl ModAction {
+    pub const ALL: [ModAction; 13] = [
+        ModAction::Kick,
+        ModAction::Ban,
+        ModAction::Unban,
+        ModAction::Timeout,
+        ModAction::Invite,
+        ModAction::BanList,
+        ModAction::Description,
+        ModAction::Mode,
+        ModAction::Op,
+        ModAction::Deop,
+        ModAction::SetOwner,
+        ModAction::Open,
+        ModAction::Close,
+    ];
+
+    /// The slash command, without the slash.
+    pub fn command(self) -> &'static str {
+        match self {
+            ModAction::Kick => "kick",
+            ModAction::Ban => "ban",
+            ModAction::Unban => "unban",
+            ModAction::Timeout => "timeout",
+            ModAction::Invite => "invite",
+            ModAction::BanList => "banlist",
+            ModAction::Description => "setdescription",
+            ModAction::Mode => "setmode",
+            ModAction::Op => "op",
+            ModAction::Deop => "deop",
+            ModAction::SetOwner => "setowner",
+            ModAction::Open => "openroom",
+            ModAction::Close => "closeroom",
+        }
+    }
+
+    pub fn from_command(command: &str) -> Option<ModAction> {
+        ModAction::ALL
+            .into_iter()
+            .find(|action| action.command() == command)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ModAction::Kick => "Kick a character",
+            ModAction::Ban => "Ban a character",
+            ModAction::Unban => "Unban a character",
+            ModAction::Timeout => "Time out a character (name, minutes)",
+            ModAction::Invite => "Invite a character",
+            ModAction::BanList => "Show the ban list",
+            ModAction::Description => "Set the description",
+            ModAction::Mode => "Allow chat, ads or both",
+            ModAction::Op => "Make a character an operator",
+            ModAction::Deop => "Remove an operator",
+            ModAction::SetOwner => "Give the channel to a character",
+            ModAction::Open => "Make the room public",
+            ModAction::Close => "Make the room private",
+        }
+    }
+
+    /// Actions only the channel owner can take. Operators can do the rest.
+    pub fn owner_only(self) -> bool {
+        matches!(
+            self,
+            ModAction::Op
+                | ModAction::Deop
+                | ModAction::SetOwner
+                | ModAction::Open
+                | ModAction::Close
+        )
+    }
+
+    fn takes_argument(self) -> bool {
+        !matches!(
+            self,
+            ModAction::BanList | ModAction::Open | ModAction::Close
+        )
+    }
+
+    /// Looking at the ban list changes nothing, so it doesn't need to be confirmed.
+    pub fn needs_confirmation(self) -> bool {
+        self != ModAction::BanList
+    }
+
+    /// The message to send for this action in `channel`, with the rest of the command line as
+    /// `argument`.
+    pub fn message(self, channel: &str, argument: &str) -> Result<ClientMessage, String> {
+        let channel = channel.to_owned();
+        let argument = argument.trim();
+        if self.takes_argument() && argument.is_empty() {
+            return Err(format!("/{} needs an argument", self.command()));
+        }
+        let character = argument.to_owned();
+        let message = match self {
+            ModAction::Kick => ClientMessage::CKU { channel, character },
+            ModAction::Ban => ClientMessage::CBU { character, channel },
+            ModAction::Unban => ClientMessage::CUB { channel, character },
+            ModAction::Timeout => {
+                // Names can contain spaces, so the length comes after a comma
+                let (character, length) = argument
+                    .rsplit_once(',')
+                    .ok_or("Usage: /timeout character, minutes")?;
+                let length = length
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|length| (1..=90).contains(length))
+                    .ok_or("Timeouts are 1 to 90 minutes long")?;
+                ClientMessage::CTU {
+                    channel,
+                    character: character.trim().to_owned(),
+                    length,
+                }
+            }
+            ModAction::Invite => ClientMessage::CIU { channel, character },
+            ModAction::BanList => ClientMessage::CBL { channel },
+            ModAction::Description => ClientMessage::CDS {
+                channel,
+                description: argument.to_owned(),
+            },
+            ModAction::Mode => {
+                let mode = match argument.to_lowercase().as_str() {
+                    "chat" => ChannelMode::ChatOnly,
+                    "ads" => ChannelMode::AdsOnly,
+                    "both" => ChannelMode::Both,
+                    _ => return Err(String::from("Usage: /setmode chat|ads|both")),
+                };
+                ClientMessage::RMO { channel, mode }
+            }
+            ModAction::Op => ClientMessage::COA { channel, character },
+            ModAction::Deop => ClientMessage::COR { channel, character },
+            ModAction::SetOwner => ClientMessage::CSO { character, channel },
+            ModAction::Open => ClientMessage::RST {
+                channel,
+                status: String::from("public"),
+            },
+            ModAction::Close => ClientMessage::RST {
+                channel,
+                status: String::from("private"),
+            },
+        };
+        Ok(message)
+    }
+
+    /// The question asked before the action is taken.
+    pub fn confirmation(self, channel: &str, argument: &str) -> String {
+        let argument = argument.trim();
+        match self {
+            ModAction::Kick => format!("Kick {} from {}?", argument, channel),
+            ModAction::Ban => format!("Ban {} from {}?", argument, channel),
+            ModAction::Unban => format!("Unban {} from {}?", argument, channel),
+            ModAction::Timeout => {
+                let (character, length) = argument.rsplit_once(',').unwrap_or((argument, ""));
+                format!(
+                    "Time out {} in {} for {} minutes?",
+                    character.trim(),
+                    channel,
+                    length.trim()
+                )
+            }
+            ModAction::Invite => format!("Invite {} to {}?", argument, channel),
+            ModAction::BanList => format!("Show the ban list of {}?", channel),
+            ModAction::Description => format!("Replace the description of {}?", channel),
+            ModAction::Mode => format!("Set {} to allow {}?", channel, argument),
+            ModAction::Op => format!("Make {} an operator of {}?", argument, channel),
+            ModAction::Deop => format!("Remove {} as an operator of {}?", argument, channel),
+            ModAction::SetOwner => format!(
+                "Give {} to {}? You won't be its owner anymore.",
+                channel, argument
+            ),
+            ModAction::Open => format!("Make {} public, for everyone to find?", channel),
+            ModAction::Close => format!("Make {} private, invite only?", channel),
+        }
+    }
+}
+
+pub enum MenuAction {
+    Cancel,
+    /// Start typing the command for this action.
+    Pick(ModAction),
+}
+
+/// Lists the moderation actions we're allowed to take in a channel.
+pub struct ModerationMenu {
+    actions: Vec<ModAction>,
+    list_state: ListState,
+}
+
+impl ModerationMenu {
+    pub fn new(owner: bool) -> Self {
+        ModerationMenu {
+            actions: ModAction::ALL
+                .into_iter()
+                .filter(|action| owner || !action.owner_only())
+                .collect(),
+            list_state: ListState::default().with_selected(Some(0)),
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination) -> Option<MenuAction> {
+        match key {
+            key!(esc) => return Some(MenuAction::Cancel),
+            key!(enter) => {
+                let index = self.list_state.selected()?;
+                return Some(MenuAction::Pick(*self.actions.get(index)?));
+            }
+            key!(up) => self.list_state.select_previous(),
+            key!(down) => self.list_state.select_next(),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let items = self
+            .actions
+            .iter()
+            .map(|action| format!("/{:<16}{}", action.command(), action.label()));
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(
+            List::new(items)
+                .highlight_symbol("> ")
+                .block(Block::bordered().title("Moderation (Enter: pick, Esc: close)")),
+            area,
+            &mut self.list_state,
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use clap::ValueEnum;
+
+use crate::chat::Target;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Trigger {
+    /// A new private message.
+    Private,
+    /// A mention of our character in a channel.
+    Mention,
+    /// A friend came online.
+    Friend,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// Ring the terminal bell.
+    Bell,
+    /// Ask the terminal to set the urgent hint on its window. Sends a bell, which most terminals
+    /// turn into the hint, and iTerm2's own OSC 1337 request, which others ignore.
+    Urgent,
+    /// Desktop notification through OSC 9.
+    Osc9,
+    /// Desktop notification through OSC 777.
+    Osc777,
+}
+
+pub struct Notifier {
+    triggers: Vec<Trigger>,
+    methods: Vec<Method>,
+    command: Option<String>,
+    muted: HashSet<Target>,
+    do_not_disturb: bool,
+}
+
+impl Notifier {
+    pub fn new(triggers: Vec<Trigger>, methods: Vec<Method>, command: Option<String>) -> Self {
+        Notifier {
+            triggers,
+            methods,
+            command,
+            muted: HashSet::new(),
+            do_not_disturb: false,
+        }
+    }
+
+    /// Flips the mute state of a conversation, returning whether it is now muted.
+    pub fn toggle_mute(&mut self, target: Target) -> bool {
+        if self.muted.remove(&target) {
+            false
+        } else {
+            self.muted.insert(target);
+            true
+        }
+    }
+
+    pub fn set_do_not_disturb(&mut self, do_not_disturb: bool) {
+        self.do_not_disturb = do_not_disturb;
+    }
+
+    pub fn notify(&self, trigger: Trigger, target: &Target, title: &str, body: &str) {
+        if self.do_not_disturb || !self.triggers.contains(&trigger) || self.muted.contains(target) {
+            return;
+        }
+        // Notifications are best effort, a terminal that went away shouldn't take the app with it
+        let _ = self.write_sequences(title, body);
+        if let Some(command) = &self.command {
+            let _ = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("RSFCHAT_TITLE", title)
+                .env("RSFCHAT_BODY", body)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+        }
+    }
+
+    fn write_sequences(&self, title: &str, body: &str) -> io::Result<()> {
+        let title = sanitize(title);
+        let body = sanitize(body);
+        let mut stdout = io::stdout().lock();
+        for method in &self.methods {
+            match method {
+                Method::Bell => write!(stdout, "\x07")?,
+                Method::Urgent => write!(stdout, "\x07\x1b]1337;RequestAttention=yes\x07")?,
+                Method::Osc9 => write!(stdout, "\x1b]9;{}: {}\x07", title, body)?,
+                Method::Osc777 => write!(stdout, "\x1b]777;notify;{};{}\x07", title, body)?,
+            }
+        }
+        stdout.flush()
+    }
+}
+
+/// Strips control characters and `;`, which would end or split the OSC sequence early.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if c == ';' { ',' } else { c })
+        .collect()
+}
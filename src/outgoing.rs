@@ -0,0 +1,276 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::time::Duration;
+
+use fchat::ClientMessage;
+use futures::{Sink, SinkExt};
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
+use tokio::time::{Instant, sleep_until};
+
+use crate::app::AppEvent;
+use crate::console;
+
+/// Kept on top of every flood limit, so timer jitter can't put us over one.
+const MARGIN: Duration = Duration::from_millis(100);
+
+/// Requests for the task that sends messages to the server.
+#[derive(Debug)]
+pub enum Outgoing {
+    /// Sends a message once the server's flood limits allow it. Messages with an id are
+    /// reported with `AppEvent::Sent` once they went out.
+    Message {
+        id: Option<u64>,
+        message: ClientMessage,
+    },
+    /// Takes a message out of the queue if it wasn't sent yet, reported with
+    /// `AppEvent::Cancelled`.
+    Cancel(u64),
+    /// A flood limit the server sent in VAR.
+    Limit { variable: String, seconds: f64 },
+}
+
+/// Messages that count towards the same flood limit.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Flood {
+    Message,
+    /// Ads are limited per channel.
+    Ad(String),
+}
+
+impl Flood {
+    fn of(message: &ClientMessage) -> Option<Flood> {
+        match message {
+            ClientMessage::MSG { .. } | ClientMessage::PRI { .. } => Some(Flood::Message),
+            ClientMessage::LRP { channel, .. } => Some(Flood::Ad(channel.clone())),
+            _ => None,
+        }
+    }
+}
+
+struct Queued {
+    id: Option<u64>,
+    message: ClientMessage,
+    flood: Option<Flood>,
+}
+
+/// Paces outgoing messages by the flood limits the server advertises, so it never has to
+/// reject them or disconnect us.
+struct Scheduler {
+    queue: VecDeque<Queued>,
+    /// `msg_flood`
+    message_interval: Duration,
+    /// `lfrp_flood`
+    ad_interval: Duration,
+    last_sent: HashMap<Flood, Instant>,
+}
+
+impl Scheduler {
+    fn new() -> Self {
+        Scheduler {
+            queue: VecDeque::new(),
+            message_interval: Duration::from_millis(500),
+            ad_interval: Duration::from_secs(600),
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// When a message limited by `flood` can be sent next. Messages without a limit can always
+    /// be sent.
+    fn ready_at(&self, flood: &Option<Flood>) -> Option<Instant> {
+        let flood = flood.as_ref()?;
+        let interval = match flood {
+            Flood::Message => self.message_interval,
+            Flood::Ad(_) => self.ad_interval,
+        };
+        self.last_sent
+            .get(flood)
+            .map(|sent| *sent + interval + MARGIN)
+    }
+
+    /// The first queued message that can be sent now. Messages under the same limit all become
+    /// ready at the same time, so they still go out in order.
+    fn ready(&self) -> Option<usize> {
+        let now = Instant::now();
+        self.queue.iter().position(|queued| {
+            self.ready_at(&queued.flood)
+                .is_none_or(|ready_at| ready_at <= now)
+        })
+    }
+
+    fn next_ready_at(&self) -> Option<Instant> {
+        self.queue
+            .iter()
+            .filter_map(|queued| self.ready_at(&queued.flood))
+            .min()
+    }
+
+    fn request(&mut self, request: Outgoing, events: &UnboundedSender<AppEvent>) {
+        match request {
+            Outgoing::Message { id, message } => self.queue.push_back(Queued {
+                id,
+                flood: Flood::of(&message),
+                message,
+            }),
+            Outgoing::Cancel(id) => {
+                if let Some(index) = self.queue.iter().position(|queued| queued.id == Some(id)) {
+                    self.queue.remove(index);
+                    let _ = events.send(AppEvent::Cancelled(id));
+                }
+            }
+            Outgoing::Limit { variable, seconds } => {
+                let interval = Duration::from_secs_f64(seconds.max(0.0));
+                match variable.as_str() {
+                    "msg_flood" => self.message_interval = interval,
+                    "lfrp_flood" => self.ad_interval = interval,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Sends everything that comes in on `requests`, as fast as the flood limits allow.
+pub async fn run<S>(
+    mut sink: S,
+    mut requests: Receiver<Outgoing>,
+    events: UnboundedSender<AppEvent>,
+) where
+    S: Sink<ClientMessage> + Unpin,
+    S::Error: Debug,
+{
+    let mut scheduler = Scheduler::new();
+    loop {
+        while let Some(index) = scheduler.ready() {
+            let Some(queued) = scheduler.queue.remove(index) else {
+                break;
+            };
+            if let Some(flood) = queued.flood {
+                scheduler.last_sent.insert(flood, Instant::now());
+            }
+            let frame = console::wire_text(&queued.message);
+            if let Err(error) = sink.send(queued.message).await {
+                // Nothing more can be sent, the app finds out from here
+                let _ = events.send(AppEvent::Disconnected(format!("{:?}", error)));
+                return;
+            }
+            let _ = events.send(AppEvent::Outbound(frame));
+            if let Some(id) = queued.id {
+                let _ = events.send(AppEvent::Sent(id));
+            }
+        }
+        let request = match scheduler.next_ready_at() {
+            Some(ready_at) => tokio::select! {
+                request = requests.recv() => request,
+                () = sleep_until(ready_at) => continue,
+            },
+            None => requests.recv().await,
+        };
+        let Some(request) = request else {
+            return;
+        };
+        scheduler.request(request, &events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn message(text: &str) -> Outgoing {
+        Outgoing::Message {
+            id: None,
+            message: ClientMessage::MSG {
+                channel: String::from("Frontpage"),
+                message: text.to_owned(),
+            },
+        }
+    }
+
+    fn ad(channel: &str) -> Outgoing {
+        Outgoing::Message {
+            id: None,
+            message: ClientMessage::LRP {
+                channel: channel.to_owned(),
+                message: String::from("Looking for a scene"),
+            },
+        }
+    }
+
+    #[test]
+    fn messages_are_paced_by_msg_flood() {
+        let (events, _) = unbounded_channel();
+        let mut scheduler = Scheduler::new();
+        let limit = Outgoing::Limit {
+            variable: String::from("msg_flood"),
+            seconds: 2.0,
+        };
+        scheduler.request(limit, &events);
+        scheduler.request(message("first"), &events);
+        assert_eq!(scheduler.ready(), Some(0));
+
+        let sent = Instant::now();
+        scheduler.last_sent.insert(Flood::Message, sent);
+        assert_eq!(scheduler.ready(), None);
+        assert_eq!(
+            scheduler.next_ready_at(),
+            Some(sent + Duration::from_secs(2) + MARGIN)
+        );
+
+        scheduler
+            .last_sent
+            .insert(Flood::Message, sent - Duration::from_secs(3));
+        assert_eq!(scheduler.ready(), Some(0));
+    }
+
+    #[test]
+    fn ads_are_paced_per_channel_by_lfrp_flood() {
+        let (events, _) = unbounded_channel();
+        let mut scheduler = Scheduler::new();
+        let limit = Outgoing::Limit {
+            variable: String::from("lfrp_flood"),
+            seconds: 60.0,
+        };
+        scheduler.request(limit, &events);
+        scheduler.request(ad("Frontpage"), &events);
+        scheduler.request(ad("Sex Driven LFRP"), &events);
+
+        let sent = Instant::now() - Duration::from_secs(30);
+        scheduler
+            .last_sent
+            .insert(Flood::Ad(String::from("Frontpage")), sent);
+        // The other channel doesn't have to wait for this one
+        assert_eq!(scheduler.ready(), Some(1));
+        scheduler.queue.remove(1);
+        assert_eq!(scheduler.ready(), None);
+        assert_eq!(
+            scheduler.next_ready_at(),
+            Some(sent + Duration::from_secs(60) + MARGIN)
+        );
+    }
+
+    #[test]
+    fn cancelling_takes_a_pending_message_out_of_the_queue() {
+        let (events, mut received) = unbounded_channel();
+        let mut scheduler = Scheduler::new();
+        scheduler.request(message("first"), &events);
+        scheduler.request(
+            Outgoing::Message {
+                id: Some(7),
+                message: ClientMessage::MSG {
+                    channel: String::from("Frontpage"),
+                    message: String::from("second"),
+                },
+            },
+            &events,
+        );
+        scheduler.request(Outgoing::Cancel(7), &events);
+        assert_eq!(scheduler.queue.len(), 1);
+        assert!(matches!(received.try_recv(), Ok(AppEvent::Cancelled(7))));
+
+        // Already sent or never queued, nothing to report
+        scheduler.request(Outgoing::Cancel(7), &events);
+        assert!(received.try_recv().is_err());
+    }
+}
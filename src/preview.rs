@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+
+use crokey::{KeyCombination, key};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, Paragraph, Wrap},
+};
+use ratatui_image::protocol::StatefulProtocol;
+use ratatui_image::{Resize, StatefulImage};
+
+use crate::bbcode;
+use crate::fetch::FetchRequest;
+
+/// Domains that are never fetched, subdomains included. Also checked on every redirect.
+#[derive(Clone, Debug)]
+pub struct Blocklist(Vec<String>);
+
+impl Blocklist {
+    pub fn new(domains: &[String]) -> Self {
+        Blocklist(
+            domains
+                .iter()
+                .map(|domain| domain.trim_start_matches('.').to_lowercase())
+                .collect(),
+        )
+    }
+
+    /// Whether `url` is on a blocked domain. Anything that isn't http or https is.
+    pub fn blocks(&self, url: &str) -> bool {
+        let Some(host) = host(url) else {
+            return true;
+        };
+        self.0
+            .iter()
+            .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+    }
+}
+
+/// Decides what may be previewed and where downloads are kept.
+pub struct Previews {
+    cache_dir: PathBuf,
+    max_bytes: u64,
+    blocklist: Blocklist,
+    /// Whether links are downloaded as they're posted, so they open right away.
+    auto: bool,
+}
+
+impl Previews {
+    pub fn new(cache_dir: PathBuf, max_kib: u64, blocklist: Blocklist, auto: bool) -> Self {
+        Previews {
+            cache_dir,
+            auto,
+            max_bytes: max_kib * 1024,
+            blocklist,
+        }
+    }
+
+    pub fn blocked(&self, url: &str) -> bool {
+        self.blocklist.blocks(url)
+    }
+
+    /// Where a link's image goes. Only images are ever written there. Named by a hash that
+    /// stays the same across builds, so the cache outlives toolchain updates.
+    pub fn path(&self, url: &str) -> PathBuf {
+        self.cache_dir
+            .join(format!("{:016x}", fnv1a(url.as_bytes())))
+    }
+
+    pub fn request(&self, url: &str) -> FetchRequest {
+        FetchRequest {
+            url: url.to_owned(),
+            path: self.path(url),
+            max_bytes: self.max_bytes,
+            images_only: true,
+        }
+    }
+
+    pub fn cached(&self, url: &str) -> Option<PathBuf> {
+        Some(self.path(url)).filter(|path| Path::exists(path))
+    }
+
+    /// Downloads for the links in a message that was just posted, if that's turned on.
+    pub fn prefetch(&self, message: &str) -> Vec<FetchRequest> {
+        if !self.auto {
+            return Vec::new();
+        }
+        bbcode::urls(message)
+            .into_iter()
+            .filter(|url| !self.blocked(url) && self.cached(url).is_none())
+            .map(|url| self.request(&url))
+            .collect()
+    }
+}
+
+/// 64-bit FNV-1a.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The lowercased host of an http or https address.
+fn host(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+enum Content {
+    Loading,
+    Image(StatefulProtocol),
+    Failed(String),
+}
+
+/// Popup showing the image behind a link.
+pub struct PreviewPopup {
+    pub url: String,
+    /// What the server said it sent, when it was just downloaded.
+    pub content_type: Option<String>,
+    content: Content,
+}
+
+impl PreviewPopup {
+    pub fn new(url: String) -> Self {
+        PreviewPopup {
+            url,
+            content_type: None,
+            content: Content::Loading,
+        }
+    }
+
+    pub fn loaded(&mut self, result: Result<StatefulProtocol, String>) {
+        self.content = match result {
+            Ok(protocol) => Content::Image(protocol),
+            Err(error) => Content::Failed(error),
+        };
+    }
+
+    /// Whether the key closes it.
+    pub fn key(&mut self, key: KeyCombination) -> bool {
+        key == key!(esc) || key == key!(enter) || key == key!(q)
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let title = match &self.content_type {
+            Some(content_type) => format!("{} ({}, Esc: close)", self.url, content_type),
+            None => format!("{} (Esc: close)", self.url),
+        };
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        match &mut self.content {
+            Content::Loading => frame.render_widget("Loading…", inner),
+            Content::Image(protocol) => frame.render_stateful_widget(
+                StatefulImage::new(None).resize(Resize::Fit(None)),
+                inner,
+                protocol,
+            ),
+            Content::Failed(error) => frame.render_widget(
+                Paragraph::new(format!("No preview: {}", error)).wrap(Wrap { trim: true }),
+                inner,
+            ),
+        }
+    }
+}
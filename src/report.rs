@@ -0,0 +1,323 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use crokey::{KeyCombination, key};
+use crossterm::event::{Event, KeyEvent};
+use fchat::{ClientMessage, Ticket};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    text::Text,
+    widgets::{Block, Clear, List, ListState, Paragraph, Wrap},
+};
+use ratatui_macros::vertical;
+use serde_json::Value;
+use tui_prompts::{FocusState, Prompt, State, TextPrompt, TextState};
+
+use crate::chat::ChatLine;
+use crate::storage;
+use crate::widgets::{TextArea, TextAreaState};
+
+/// How many lines of the conversation go along with a report.
+pub const CAPTURED_LINES: usize = 50;
+
+/// Where the official client uploads the log that goes with a report.
+const REPORT_URL: &str = "https://www.f-list.net/json/api/report-submit.php";
+
+/// A report we're about to send to the staff.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// The conversation it's about.
+    pub conversation: String,
+    pub character: String,
+    pub text: String,
+    pub log: Vec<String>,
+}
+
+impl Report {
+    /// The SFC alert, worded the way the staff tools expect it. `logid` is what uploading the
+    /// log returned, staff only get to see the conversation through it.
+    pub fn message(&self, reporter: &str, logid: Option<u64>) -> ClientMessage {
+        ClientMessage::SFC {
+            action: String::from("report"),
+            report: Some(format!(
+                "Current Tab/Channel: {} | Reporting User: {} | {}",
+                self.conversation, self.character, self.text
+            )),
+            character: Some(reporter.to_owned()),
+            moderator: None,
+            callid: None,
+            logid,
+        }
+    }
+
+    /// Writes the captured log under `reports`, returning where it went.
+    pub fn save_log(&self, data_dir: &Path, reporter: &str) -> io::Result<PathBuf> {
+        let dir = storage::character_dir(data_dir, reporter).join("reports");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!(
+            "{}-{}.log",
+            Local::now().format("%Y-%m-%d-%H%M%S"),
+            storage::sanitize(&self.character)
+        ));
+        let mut contents = format!(
+            "Report about {} in {}\n{}\n\n",
+            self.character, self.conversation, self.text
+        );
+        for line in &self.log {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+}
+
+/// Uploads the captured log the way the official client does, returning the id the SFC alert
+/// refers to it by.
+pub async fn upload_log(
+    client: &reqwest::Client,
+    ticket: &Ticket,
+    reporter: &str,
+    report: &Report,
+) -> Result<u64, String> {
+    let log = serde_json::to_string(&report.log).map_err(|error| error.to_string())?;
+    let form = [
+        ("account", ticket.account.as_str()),
+        ("ticket", ticket.ticket.as_str()),
+        ("character", reporter),
+        ("reportUser", report.character.as_str()),
+        ("reportText", report.text.as_str()),
+        ("channel", report.conversation.as_str()),
+        // The log is a list of lines as shown, not of messages
+        ("text", "true"),
+        ("log", log.as_str()),
+    ];
+    let body = client
+        .post(REPORT_URL)
+        .form(&form)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| error.to_string())?
+        .text()
+        .await
+        .map_err(|error| error.to_string())?;
+    let response: Value = serde_json::from_str(&body).map_err(|error| error.to_string())?;
+    if let Some(error) = response["error"].as_str()
+        && !error.is_empty()
+    {
+        return Err(error.to_owned());
+    }
+    // The site isn't consistent about numbers, so take the id either way
+    match &response["log_id"] {
+        Value::Number(id) => id.as_u64(),
+        Value::String(id) => id.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| String::from("The site didn't return a log id"))
+}
+
+pub enum ReportAction {
+    Cancel,
+    Send(Report),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Focus {
+    Character,
+    Text,
+}
+
+/// Popup for writing a report, showing the part of the conversation that goes with it.
+pub struct ReportDialog {
+    conversation: String,
+    character: TextState<'static>,
+    text: TextAreaState,
+    log: Vec<String>,
+    focus: Focus,
+}
+
+impl ReportDialog {
+    pub fn new(conversation: &str, character: &str, lines: &[ChatLine]) -> Self {
+        let mut character_state = TextState::new().with_focus(FocusState::Focused);
+        character_state.value_mut().push_str(character);
+        let start = lines.len().saturating_sub(CAPTURED_LINES);
+        ReportDialog {
+            conversation: conversation.to_owned(),
+            character: character_state,
+            text: TextAreaState::new(),
+            log: lines[start..]
+                .iter()
+                .filter(|line| line.pending.is_none())
+                .map(ChatLine::to_string)
+                .collect(),
+            focus: Focus::Character,
+        }
+    }
+
+    fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            Focus::Character => {
+                self.character.blur();
+                Focus::Text
+            }
+            Focus::Text => {
+                self.character.focus();
+                Focus::Character
+            }
+        };
+    }
+
+    pub fn key(&mut self, key: KeyCombination, event: KeyEvent) -> Option<ReportAction> {
+        match key {
+            key!(esc) => return Some(ReportAction::Cancel),
+            key!(tab) | key!(shift - tab) => self.toggle_focus(),
+            // Enter makes a new line in the report, so sending needs a key of its own
+            key!(ctrl - s) => {
+                let character = self.character.value().trim().to_owned();
+                let text = self.text.text().trim().to_owned();
+                if character.is_empty() || text.is_empty() {
+                    return None;
+                }
+                return Some(ReportAction::Send(Report {
+                    conversation: self.conversation.clone(),
+                    character,
+                    text,
+                    log: self.log.clone(),
+                }));
+            }
+            _ => match self.focus {
+                Focus::Character => {
+                    self.character.handle_key_event(event);
+                }
+                Focus::Text => self.text.event(&Event::Key(event)),
+            },
+        }
+        None
+    }
+
+    pub fn paste(&mut self, data: &str) {
+        match self.focus {
+            Focus::Character => self.character.value_mut().push_str(data),
+            Focus::Text => self.text.event(&Event::Paste(data.to_owned())),
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(format!(
+            "Report in {} (Tab: switch, Ctrl-S: send, Esc: cancel)",
+            self.conversation
+        ));
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        let [character_area, text_area, log_area] = vertical![==1, ==6, *=1].areas(inner);
+        TextPrompt::new("Reporting".into()).draw(frame, character_area, &mut self.character);
+        frame.render_stateful_widget_ref(TextArea::new(), text_area, &mut self.text);
+        // Show the end of the log, like the scrollback does
+        let height = log_area.height.saturating_sub(2) as usize;
+        let start = self.log.len().saturating_sub(height);
+        frame.render_widget(
+            Paragraph::new(
+                self.log[start..]
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Text>(),
+            )
+            .block(Block::bordered().title(format!("Sent along ({} lines)", self.log.len()))),
+            log_area,
+        );
+    }
+}
+
+/// A report from a user, as chat operators see it.
+pub struct StaffReport {
+    pub callid: u64,
+    pub character: String,
+    pub report: String,
+    pub timestamp: String,
+    /// The operator who's handling it.
+    pub claimed_by: Option<String>,
+}
+
+pub enum QueueAction {
+    Close,
+    Claim(u64),
+    /// Drops the report from the list, without telling anyone.
+    Dismiss(u64),
+}
+
+/// Lists the reports that came in, for chat operators to claim.
+pub struct ReportQueue {
+    list_state: ListState,
+}
+
+impl ReportQueue {
+    pub fn new() -> Self {
+        ReportQueue {
+            list_state: ListState::default().with_selected(Some(0)),
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination, reports: &[StaffReport]) -> Option<QueueAction> {
+        match key {
+            key!(esc) => return Some(QueueAction::Close),
+            key!(enter) | key!(c) => {
+                let report = reports.get(self.list_state.selected()?)?;
+                if report.claimed_by.is_none() {
+                    return Some(QueueAction::Claim(report.callid));
+                }
+            }
+            key!(delete) | key!(d) => {
+                let report = reports.get(self.list_state.selected()?)?;
+                return Some(QueueAction::Dismiss(report.callid));
+            }
+            key!(up) => self.list_state.select_previous(),
+            key!(down) => self.list_state.select_next(),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect, reports: &[StaffReport]) {
+        let block = Block::bordered().title("Reports (Enter: claim, d: dismiss, Esc: close)");
+        frame.render_widget(Clear, area);
+        if reports.is_empty() {
+            frame.render_widget(Paragraph::new("No reports.").block(block), area);
+            return;
+        }
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let [list_area, detail_area] = vertical![*=1, ==6].areas(inner);
+        let items = reports.iter().map(|report| {
+            let claimed = match &report.claimed_by {
+                Some(moderator) => format!("handled by {}", moderator),
+                None => String::from("open"),
+            };
+            format!(
+                "#{:<8}{} from {} ({})",
+                report.callid, report.timestamp, report.character, claimed
+            )
+        });
+        frame.render_stateful_widget(
+            List::new(items).highlight_symbol("> "),
+            list_area,
+            &mut self.list_state,
+        );
+        if let Some(report) = self
+            .list_state
+            .selected()
+            .and_then(|index| reports.get(index))
+        {
+            frame.render_widget(
+                Paragraph::new(report.report.as_str())
+                    .wrap(Wrap { trim: true })
+                    .block(Block::bordered()),
+                detail_area,
+            );
+        }
+    }
+}
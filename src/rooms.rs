@@ -0,0 +1,144 @@
+use crokey::{KeyCombination, key};
+use crossterm::event::{Event, KeyEvent};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, Paragraph, Wrap},
+};
+use ratatui_macros::{horizontal, vertical};
+use tui_prompts::{FocusState, Prompt, State, TextPrompt, TextState};
+
+use crate::bbcode;
+use crate::widgets::{TextArea, TextAreaState};
+
+/// A room we asked the server to create, set up once we've joined it.
+pub struct NewRoom {
+    pub title: String,
+    pub description: String,
+    pub public: bool,
+}
+
+/// The BBCode link other clients turn into a join button. BBCode has no escapes, so brackets
+/// in the title become parentheses rather than closing the tag early.
+pub fn session_link(title: &str, channel: &str) -> String {
+    let title = title.replace('[', "(").replace(']', ")");
+    format!("[session={}]{}[/session]", title, channel)
+}
+
+pub enum RoomAction {
+    Cancel,
+    Create(NewRoom),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Focus {
+    Name,
+    Description,
+    Privacy,
+}
+
+/// Popup asking for everything a new private room needs.
+pub struct RoomDialog {
+    name: TextState<'static>,
+    description: TextAreaState,
+    public: bool,
+    focus: Focus,
+}
+
+impl RoomDialog {
+    pub fn new(name: &str) -> Self {
+        let mut name_state = TextState::new().with_focus(FocusState::Focused);
+        name_state.value_mut().push_str(name);
+        RoomDialog {
+            name: name_state,
+            description: TextAreaState::new(),
+            public: false,
+            focus: Focus::Name,
+        }
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.focus = focus;
+        if focus == Focus::Name {
+            self.name.focus();
+        } else {
+            self.name.blur();
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination, event: KeyEvent) -> Option<RoomAction> {
+        match key {
+            key!(esc) => return Some(RoomAction::Cancel),
+            key!(tab) => self.set_focus(match self.focus {
+                Focus::Name => Focus::Description,
+                Focus::Description => Focus::Privacy,
+                Focus::Privacy => Focus::Name,
+            }),
+            key!(shift - tab) => self.set_focus(match self.focus {
+                Focus::Name => Focus::Privacy,
+                Focus::Description => Focus::Name,
+                Focus::Privacy => Focus::Description,
+            }),
+            // Enter is a new line in the description, so it only creates the room elsewhere
+            key!(enter) if self.focus != Focus::Description => {
+                let title = self.name.value().trim().to_owned();
+                if title.is_empty() {
+                    return None;
+                }
+                return Some(RoomAction::Create(NewRoom {
+                    title,
+                    description: self.description.text().trim().to_owned(),
+                    public: self.public,
+                }));
+            }
+            _ => match self.focus {
+                Focus::Name => {
+                    self.name.handle_key_event(event);
+                }
+                Focus::Description => self.description.event(&Event::Key(event)),
+                Focus::Privacy => {
+                    if key == key!(space) {
+                        self.public = !self.public;
+                    }
+                }
+            },
+        }
+        None
+    }
+
+    pub fn paste(&mut self, data: &str) {
+        match self.focus {
+            Focus::Name => self.name.value_mut().push_str(data),
+            Focus::Description => self.description.event(&Event::Paste(data.to_owned())),
+            Focus::Privacy => {}
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title("New room (Tab: switch, Enter: create, Esc: cancel)");
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        let [name_area, privacy_area, description_area] = vertical![==1, ==1, *=1].areas(inner);
+        let [editor_area, preview_area] = horizontal![*=1, *=1].areas(description_area);
+        TextPrompt::new("Name".into()).draw(frame, name_area, &mut self.name);
+        let marker = if self.focus == Focus::Privacy {
+            "> "
+        } else {
+            "  "
+        };
+        let privacy = if self.public {
+            "Public: anyone can find and join it (Space to change)"
+        } else {
+            "Private: invite only (Space to change)"
+        };
+        frame.render_widget(format!("{}{}", marker, privacy), privacy_area);
+        frame.render_stateful_widget_ref(TextArea::new(), editor_area, &mut self.description);
+        frame.render_widget(
+            Paragraph::new(bbcode::render(self.description.text()))
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title("Description preview")),
+            preview_area,
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use crokey::{KeyCombination, key};
+use crossterm::event::KeyEvent;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, List, ListState},
+};
+use ratatui_macros::vertical;
+use serde::{Deserialize, Serialize};
+use tui_prompts::{FocusState, Prompt, State, TextPrompt, TextState};
+
+use crate::bbcode;
+use crate::logs::{self, LogEntry};
+use crate::storage;
+
+/// What to look for. In the search screen this is written as free text mixed with filters:
+/// `in:#channel`, `from:character`, `after:YYYY-MM-DD` and `before:YYYY-MM-DD`.
+#[derive(Clone, Debug, Default)]
+pub struct SearchQuery {
+    pub conversation: Option<String>,
+    pub sender: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub text: String,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Result<SearchQuery, String> {
+        let mut query = SearchQuery::default();
+        let mut words = Vec::new();
+        for word in input.split_whitespace() {
+            match word.split_once(':') {
+                Some(("in", conversation)) => query.conversation = Some(conversation.to_owned()),
+                Some(("from", sender)) => query.sender = Some(sender.to_owned()),
+                Some(("after", date)) => query.from = Some(parse_date(date)?),
+                Some(("before", date)) => query.to = Some(parse_date(date)?),
+                _ => words.push(word),
+            }
+        }
+        query.text = words.join(" ");
+        Ok(query)
+    }
+
+    /// Conversations and dates are checked per file, so only the rest of the query is checked
+    /// here. `text` is the lowercased query text, matched against the message without BBCode
+    /// so queries match what was actually shown.
+    fn matches(&self, entry: &LogEntry, text: &str) -> bool {
+        if let Some(sender) = &self.sender
+            && !entry
+                .sender
+                .as_ref()
+                .is_some_and(|actual| actual.eq_ignore_ascii_case(sender))
+        {
+            return false;
+        }
+        text.is_empty() || bbcode::strip(&entry.text).to_lowercase().contains(text)
+    }
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("Not a YYYY-MM-DD date: {}", date))
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// The conversation's log name: `#channel` or `@character`.
+    pub conversation: String,
+    pub date: NaiveDate,
+    pub entry: LogEntry,
+}
+
+/// An indexed log file.
+#[derive(Serialize, Deserialize)]
+struct IndexedFile {
+    /// The conversation's log name, as its directory is called.
+    conversation: String,
+    date: NaiveDate,
+    /// Size of the file when it was last indexed, to notice changes made behind our back.
+    size: u64,
+    /// Entries indexed, the next one appended gets this line number.
+    lines: u32,
+}
+
+/// Where an entry is: the id of its file and its line in it.
+type Posting = (u32, u32);
+
+/// Maps every word in the logs to the entries it's in, kept on disk next to the logs so a
+/// search only reads the days that have a hit. Senders are indexed as `@name`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct LogIndex {
+    #[serde(skip)]
+    dir: PathBuf,
+    files: HashMap<u32, IndexedFile>,
+    next_file: u32,
+    words: BTreeMap<String, Vec<Posting>>,
+    /// Whether anything changed since it was loaded or saved.
+    #[serde(skip)]
+    dirty: bool,
+}
+
+/// The words an entry can be found by, lowercased, each once.
+fn entry_words(entry: &LogEntry) -> BTreeSet<String> {
+    let mut words = words(&bbcode::strip(&entry.text));
+    if let Some(sender) = &entry.sender {
+        words.insert(format!("@{}", sender.to_lowercase()));
+    }
+    words
+}
+
+fn words(text: &str) -> BTreeSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+impl LogIndex {
+    /// Loads the index of the logs in `dir`, or starts an empty one. Logs it doesn't know about
+    /// yet are indexed by the next search.
+    pub fn open(dir: PathBuf) -> Self {
+        let mut index: LogIndex = storage::load(&Self::path(&dir));
+        index.dir = dir;
+        index
+    }
+
+    fn path(dir: &Path) -> PathBuf {
+        dir.with_file_name("search-index.json")
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.dirty {
+            storage::save(&Self::path(&self.dir), self)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn file_id(&self, conversation: &str, date: NaiveDate) -> Option<u32> {
+        self.files
+            .iter()
+            .find(|(_, file)| file.conversation == conversation && file.date == date)
+            .map(|(id, _)| *id)
+    }
+
+    fn add_entry(&mut self, id: u32, line: u32, entry: &LogEntry) {
+        for word in entry_words(entry) {
+            self.words.entry(word).or_default().push((id, line));
+        }
+    }
+
+    /// Takes every entry of the given files out of the index.
+    fn forget(&mut self, ids: &HashSet<u32>) {
+        if ids.is_empty() {
+            return;
+        }
+        for id in ids {
+            self.files.remove(id);
+        }
+        self.words.retain(|_, postings| {
+            postings.retain(|(id, _)| !ids.contains(id));
+            !postings.is_empty()
+        });
+        self.dirty = true;
+    }
+
+    /// Indexes an entry that was just appended to a log file, which is now `size` bytes long.
+    /// If the file changed in some other way since it was indexed, it's left for the next
+    /// search to index again.
+    pub fn appended(&mut self, conversation: &str, entry: &LogEntry, size: u64, added: u64) {
+        let date = entry.timestamp.date_naive();
+        let id = match self.file_id(conversation, date) {
+            Some(id) if self.files[&id].size + added == size => id,
+            Some(_) => return,
+            None if size == added => {
+                let id = self.next_file;
+                self.next_file += 1;
+                self.files.insert(
+                    id,
+                    IndexedFile {
+                        conversation: conversation.to_owned(),
+                        date,
+                        size: 0,
+                        lines: 0,
+                    },
+                );
+                id
+            }
+            None => return,
+        };
+        let file = self
+            .files
+            .get_mut(&id)
+            .expect("the file was just looked up");
+        let line = file.lines;
+        file.lines += 1;
+        file.size = size;
+        self.add_entry(id, line, entry);
+        self.dirty = true;
+    }
+
+    /// Indexes a log file that was written as a whole, like an import merges them.
+    pub fn rewritten(&mut self, path: &Path, entries: &[LogEntry]) -> io::Result<()> {
+        let Some((conversation, date)) = file_key(path) else {
+            return Ok(());
+        };
+        if let Some(id) = self.file_id(&conversation, date) {
+            self.forget(&HashSet::from([id]));
+        }
+        let size = fs::metadata(path)?.len();
+        self.index_file(conversation, date, size, entries);
+        Ok(())
+    }
+
+    fn index_file(
+        &mut self,
+        conversation: String,
+        date: NaiveDate,
+        size: u64,
+        entries: &[LogEntry],
+    ) {
+        let id = self.next_file;
+        self.next_file += 1;
+        for (line, entry) in entries.iter().enumerate() {
+            self.add_entry(id, line as u32, entry);
+        }
+        self.files.insert(
+            id,
+            IndexedFile {
+                conversation,
+                date,
+                size,
+                lines: entries.len() as u32,
+            },
+        );
+        self.dirty = true;
+    }
+
+    /// Brings the index up to date with the log files: new and changed ones are indexed, the
+    /// ones that are gone are forgotten.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        let mut unseen: HashSet<u32> = self.files.keys().copied().collect();
+        let mut stale = Vec::new();
+        let Ok(conversations) = fs::read_dir(&self.dir) else {
+            self.forget(&unseen);
+            return Ok(());
+        };
+        for conversation in conversations {
+            let conversation = conversation?.path();
+            if !conversation.is_dir() {
+                continue;
+            }
+            for (_, path) in logs::log_files(&conversation)? {
+                let Some((name, date)) = file_key(&path) else {
+                    continue;
+                };
+                let size = fs::metadata(&path)?.len();
+                match self.file_id(&name, date) {
+                    Some(id) if self.files[&id].size == size => {
+                        unseen.remove(&id);
+                    }
+                    _ => stale.push((name, date, size, path)),
+                }
+            }
+        }
+        // Changed files are still in `unseen`, so they're indexed again from scratch
+        self.forget(&unseen);
+        for (name, date, size, path) in stale {
+            let entries = logs::read_entries(&path)?;
+            self.index_file(name, date, size, &entries);
+        }
+        Ok(())
+    }
+
+    /// The entries that have every word of `text` as the start of one of their words, and
+    /// were sent by `sender` if one is given. `None` when neither narrows anything down.
+    fn candidates(&self, text: &str, sender: Option<&str>) -> Option<BTreeSet<Posting>> {
+        let mut candidates: Option<BTreeSet<Posting>> = None;
+        let mut narrow = |postings: BTreeSet<Posting>| {
+            candidates = Some(match candidates.take() {
+                Some(candidates) => candidates.intersection(&postings).copied().collect(),
+                None => postings,
+            });
+        };
+        for word in words(text) {
+            let postings = self
+                .words
+                .range(word.clone()..)
+                .take_while(|(indexed, _)| indexed.starts_with(&word))
+                // Senders only match `from:`
+                .filter(|(indexed, _)| !indexed.starts_with('@'))
+                .flat_map(|(_, postings)| postings.iter().copied())
+                .collect();
+            narrow(postings);
+        }
+        if let Some(sender) = sender {
+            let key = format!("@{}", sender.to_lowercase());
+            narrow(
+                self.words
+                    .get(&key)
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .collect(),
+            );
+        }
+        candidates
+    }
+
+    /// Matching entries, newest first. Only the days with a hit in the index are read.
+    pub fn search(&mut self, query: &SearchQuery, limit: usize) -> io::Result<Vec<SearchResult>> {
+        self.refresh()?;
+        let candidates = self.candidates(&query.text, query.sender.as_deref());
+        let mut files: Vec<(&u32, &IndexedFile)> = self
+            .files
+            .iter()
+            .filter(|(_, file)| {
+                query
+                    .conversation
+                    .as_ref()
+                    .is_none_or(|wanted| wanted.eq_ignore_ascii_case(&file.conversation))
+                    && query.from.is_none_or(|from| file.date >= from)
+                    && query.to.is_none_or(|to| file.date <= to)
+            })
+            .collect();
+        files.sort_by_key(|(_, file)| Reverse(file.date));
+        let text = query.text.to_lowercase();
+        let mut results = Vec::new();
+        for (id, file) in files {
+            let lines: Option<HashSet<u32>> = candidates.as_ref().map(|candidates| {
+                candidates
+                    .range((*id, 0)..=(*id, u32::MAX))
+                    .map(|(_, line)| *line)
+                    .collect()
+            });
+            if lines.as_ref().is_some_and(HashSet::is_empty) {
+                continue;
+            }
+            let path = self
+                .dir
+                .join(&file.conversation)
+                .join(format!("{}.jsonl", file.date.format("%Y-%m-%d")));
+            for (line, entry) in logs::read_entries(&path)?.into_iter().enumerate() {
+                if lines
+                    .as_ref()
+                    .is_some_and(|lines| !lines.contains(&(line as u32)))
+                {
+                    continue;
+                }
+                // The index finds words, the whole text still has to be there as written
+                if query.matches(&entry, &text) {
+                    results.push(SearchResult {
+                        conversation: file.conversation.clone(),
+                        date: file.date,
+                        entry,
+                    });
+                }
+            }
+        }
+        self.save()?;
+        results.sort_by_key(|result| Reverse(result.entry.timestamp));
+        results.truncate(limit);
+        Ok(results)
+    }
+}
+
+/// The conversation and day a log file holds, from its path.
+fn file_key(path: &Path) -> Option<(String, NaiveDate)> {
+    let date = NaiveDate::parse_from_str(path.file_stem()?.to_str()?, "%Y-%m-%d").ok()?;
+    let conversation = path.parent()?.file_name()?.to_str()?.to_owned();
+    Some((conversation, date))
+}
+
+pub fn describe(result: &SearchResult) -> String {
+    format!(
+        "{} {} <{}> {}",
+        result.entry.timestamp.format("%Y-%m-%d %H:%M"),
+        result.conversation,
+        result.entry.sender.as_deref().unwrap_or_default(),
+        bbcode::strip(&result.entry.text).replace('\n', " ")
+    )
+}
+
+pub enum SearchAction {
+    Cancel,
+    Search(String),
+    Jump(SearchResult),
+}
+
+pub struct SearchScreen {
+    input: TextState<'static>,
+    /// Whether the input changed since the results were last updated.
+    dirty: bool,
+    results: Vec<SearchResult>,
+    list_state: ListState,
+    status: String,
+}
+
+impl SearchScreen {
+    pub fn new(query: &str) -> Self {
+        let mut input = TextState::new().with_focus(FocusState::Focused);
+        input.value_mut().push_str(query);
+        SearchScreen {
+            input,
+            dirty: true,
+            results: Vec::new(),
+            list_state: ListState::default(),
+            status: String::from("Enter: search, Esc: close"),
+        }
+    }
+
+    pub fn set_results(&mut self, results: Result<Vec<SearchResult>, String>) {
+        self.dirty = false;
+        match results {
+            Ok(results) => {
+                self.status = format!(
+                    "{} results. Up/Down: select, Enter: jump to message, Esc: close",
+                    results.len()
+                );
+                self.list_state.select((!results.is_empty()).then_some(0));
+                self.results = results;
+            }
+            Err(error) => {
+                self.status = error;
+                self.results.clear();
+                self.list_state.select(None);
+            }
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination, event: KeyEvent) -> Option<SearchAction> {
+        match key {
+            key!(esc) => return Some(SearchAction::Cancel),
+            key!(enter) => {
+                if self.dirty {
+                    return Some(SearchAction::Search(self.input.value().to_owned()));
+                }
+                let selected = self.list_state.selected()?;
+                return Some(SearchAction::Jump(self.results.get(selected)?.clone()));
+            }
+            key!(up) => self.list_state.select_previous(),
+            key!(down) => self.list_state.select_next(),
+            _ => {
+                self.input.handle_key_event(event);
+                self.dirty = true;
+            }
+        }
+        None
+    }
+
+    pub fn paste(&mut self, data: &str) {
+        self.input.value_mut().push_str(data);
+        self.dirty = true;
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let block =
+            Block::bordered().title("Search logs (in:#channel from:name after:date before:date)");
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        let [input_area, status_area, results_area] = vertical![==1, ==1, *=1].areas(inner);
+        TextPrompt::new("Search".into()).draw(frame, input_area, &mut self.input);
+        frame.render_widget(self.status.as_str(), status_area);
+        frame.render_stateful_widget(
+            List::new(self.results.iter().map(describe)).highlight_symbol("> "),
+            results_area,
+            &mut self.list_state,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use super::*;
+    use crate::chat::LineKind;
+
+    fn entry(sender: &str, text: &str) -> LogEntry {
+        LogEntry {
+            timestamp: Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            sender: Some(sender.to_owned()),
+            kind: LineKind::Message,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn index_finds_rewritten_days_by_word_prefix_and_sender() {
+        let dir = std::env::temp_dir().join(format!("rsfchat-index-test-{}", std::process::id()));
+        let path = dir.join("#Lobby").join("2024-05-01.jsonl");
+        let entries = [
+            entry("Alice", "[b]Hello[/b] there"),
+            entry("Bob", "hello again"),
+        ];
+        logs::write_entries(&path, &entries).unwrap();
+        let mut index = LogIndex::open(dir.clone());
+        index.rewritten(&path, &entries).unwrap();
+        let query = SearchQuery::parse("hel from:alice").unwrap();
+        let results = index.search(&query, 10).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].conversation, "#Lobby");
+        assert_eq!(results[0].entry.text, "[b]Hello[/b] there");
+    }
+}
@@ -0,0 +1,115 @@
+use fchat::ClientMessage;
+
+/// What global chat operators can do, each with the slash command that does it. These commands
+/// don't exist for anyone the server doesn't list as an op.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StaffAction {
+    Kick,
+    AccountBan,
+    Timeout,
+    Unban,
+    Reward,
+    Broadcast,
+    Op,
+    Deop,
+    /// Claim a staff alert, by its call id.
+    Confirm,
+}
+
+impl StaffAction {
+    pub const ALL: [StaffAction; 9] = [
+        StaffAction::Kick,
+        StaffAction::AccountBan,
+        StaffAction::Timeout,
+        StaffAction::Unban,
+        StaffAction::Reward,
+        StaffAction::Broadcast,
+        StaffAction::Op,
+        StaffAction::Deop,
+        StaffAction::Confirm,
+    ];
+
+    /// The slash command, without the slash.
+    pub fn command(self) -> &'static str {
+        match self {
+            StaffAction::Kick => "gkick",
+            StaffAction::AccountBan => "accountban",
+            StaffAction::Timeout => "gtimeout",
+            StaffAction::Unban => "gunban",
+            StaffAction::Reward => "reward",
+            StaffAction::Broadcast => "broadcast",
+            StaffAction::Op => "gop",
+            StaffAction::Deop => "gdeop",
+            StaffAction::Confirm => "confirmreport",
+        }
+    }
+
+    pub fn from_command(command: &str) -> Option<StaffAction> {
+        StaffAction::ALL
+            .into_iter()
+            .find(|action| action.command() == command)
+    }
+
+    /// The message to send for this action, with the rest of the command line as `argument`.
+    /// `character` is who we're connected as.
+    pub fn message(self, argument: &str, character: &str) -> Result<ClientMessage, String> {
+        let argument = argument.trim();
+        if argument.is_empty() {
+            return Err(format!("/{} needs an argument", self.command()));
+        }
+        let target = argument.to_owned();
+        let message = match self {
+            StaffAction::Kick => ClientMessage::KIK { character: target },
+            StaffAction::AccountBan => ClientMessage::ACB { character: target },
+            StaffAction::Timeout => {
+                // Names can contain spaces, so the other parts come after commas
+                let usage = "Usage: /gtimeout character, minutes, reason";
+                let mut parts = argument.splitn(3, ',').map(str::trim);
+                let (Some(character), Some(time), Some(reason)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(String::from(usage));
+                };
+                let time = time.parse().ok().filter(|time| *time > 0).ok_or(usage)?;
+                ClientMessage::TMO {
+                    character: character.to_owned(),
+                    time,
+                    reason: reason.to_owned(),
+                }
+            }
+            StaffAction::Unban => ClientMessage::UNB { character: target },
+            StaffAction::Reward => ClientMessage::RWD { character: target },
+            StaffAction::Broadcast => ClientMessage::BRO { message: target },
+            StaffAction::Op => ClientMessage::AOP { character: target },
+            StaffAction::Deop => ClientMessage::DOP { character: target },
+            StaffAction::Confirm => ClientMessage::SFC {
+                action: String::from("confirm"),
+                report: None,
+                character: None,
+                moderator: Some(character.to_owned()),
+                callid: Some(argument.parse().map_err(|_| "Not a call id")?),
+                logid: None,
+            },
+        };
+        Ok(message)
+    }
+
+    /// The question asked before the action is taken.
+    pub fn confirmation(self, argument: &str) -> String {
+        let argument = argument.trim();
+        match self {
+            StaffAction::Kick => format!("Kick {} from the server?", argument),
+            StaffAction::AccountBan => format!("Ban the account of {}?", argument),
+            StaffAction::Timeout => {
+                let character = argument.split(',').next().unwrap_or_default().trim();
+                format!("Time out {} from the server?", character)
+            }
+            StaffAction::Unban => format!("Lift the ban or timeout of {}?", argument),
+            StaffAction::Reward => format!("Reward {}?", argument),
+            StaffAction::Broadcast => format!("Broadcast to everyone: {}", argument),
+            StaffAction::Op => format!("Make {} a global operator?", argument),
+            StaffAction::Deop => format!("Remove {} as a global operator?", argument),
+            StaffAction::Confirm => format!("Claim report {}?", argument),
+        }
+    }
+}
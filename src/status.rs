@@ -0,0 +1,172 @@
+use crokey::{KeyCombination, key};
+use crossterm::event::{Event, KeyEvent};
+use fchat::Status;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    widgets::{Block, Clear, List, ListState, Paragraph, Wrap},
+};
+use ratatui_macros::{horizontal, vertical};
+use serde::{Deserialize, Serialize};
+
+use crate::bbcode;
+use crate::widgets::{TextArea, TextAreaState};
+
+/// The statuses a user is allowed to pick for themselves.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Availability {
+    #[default]
+    Online,
+    Looking,
+    Busy,
+    Away,
+    Dnd,
+}
+
+impl Availability {
+    pub const ALL: [Availability; 5] = [
+        Availability::Online,
+        Availability::Looking,
+        Availability::Busy,
+        Availability::Away,
+        Availability::Dnd,
+    ];
+
+    pub fn parse(name: &str) -> Option<Availability> {
+        Availability::ALL
+            .into_iter()
+            .find(|availability| format!("{:?}", availability).eq_ignore_ascii_case(name))
+    }
+
+    pub fn from_status(status: Status) -> Option<Availability> {
+        let availability = match status {
+            Status::Online => Availability::Online,
+            Status::Looking => Availability::Looking,
+            Status::Busy => Availability::Busy,
+            Status::Away => Availability::Away,
+            Status::DND => Availability::Dnd,
+            _ => return None,
+        };
+        Some(availability)
+    }
+
+    pub fn status(self) -> Status {
+        match self {
+            Availability::Online => Status::Online,
+            Availability::Looking => Status::Looking,
+            Availability::Busy => Status::Busy,
+            Availability::Away => Status::Away,
+            Availability::Dnd => Status::DND,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Availability::Online => "Online",
+            Availability::Looking => "Looking",
+            Availability::Busy => "Busy",
+            Availability::Away => "Away",
+            Availability::Dnd => "Do not disturb",
+        }
+    }
+}
+
+/// Our own status, as sent with STA. Also what gets remembered between launches.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnStatus {
+    pub availability: Availability,
+    pub message: String,
+}
+
+pub enum StatusAction {
+    Cancel,
+    Submit(OwnStatus),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Focus {
+    List,
+    Message,
+}
+
+pub struct StatusEditor {
+    list_state: ListState,
+    message: TextAreaState,
+    focus: Focus,
+}
+
+impl StatusEditor {
+    pub fn new(current: &OwnStatus) -> Self {
+        let selected = Availability::ALL
+            .iter()
+            .position(|availability| *availability == current.availability);
+        StatusEditor {
+            list_state: ListState::default().with_selected(selected),
+            message: TextAreaState::with_text(current.message.clone()),
+            focus: Focus::List,
+        }
+    }
+
+    pub fn key(&mut self, key: KeyCombination, event: KeyEvent) -> Option<StatusAction> {
+        match key {
+            key!(esc) => return Some(StatusAction::Cancel),
+            key!(enter) => {
+                let index = self.list_state.selected().unwrap_or_default();
+                // select_next only stops at the end of the list once it has been drawn
+                let availability = Availability::ALL.get(index).copied()?;
+                return Some(StatusAction::Submit(OwnStatus {
+                    availability,
+                    message: self.message.text().trim().to_owned(),
+                }));
+            }
+            key!(tab) | key!(shift - tab) => {
+                self.focus = match self.focus {
+                    Focus::List => Focus::Message,
+                    Focus::Message => Focus::List,
+                };
+            }
+            _ => match self.focus {
+                Focus::List => match key {
+                    key!(up) => self.list_state.select_previous(),
+                    key!(down) => self.list_state.select_next(),
+                    _ => {}
+                },
+                Focus::Message => self.message.event(&Event::Key(event)),
+            },
+        }
+        None
+    }
+
+    pub fn paste(&mut self, data: &str) {
+        if self.focus == Focus::Message {
+            self.message.event(&Event::Paste(data.to_owned()));
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title("Status (Tab: switch, Enter: set, Esc: cancel)");
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        let [list_area, message_area] = horizontal![==16, *=1].areas(inner);
+        let [editor_area, preview_area] = vertical![*=1, *=1].areas(message_area);
+        let highlight = if self.focus == Focus::List {
+            "> "
+        } else {
+            "  "
+        };
+        frame.render_stateful_widget(
+            List::new(Availability::ALL.map(Availability::label)).highlight_symbol(highlight),
+            list_area,
+            &mut self.list_state,
+        );
+        frame.render_stateful_widget_ref(TextArea::new(), editor_area, &mut self.message);
+        frame.render_widget(
+            Paragraph::new(bbcode::render(self.message.text()))
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title("Preview")),
+            preview_area,
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+pub fn default_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("rsfchat")
+}
+
+/// Everything we keep about a single character lives under this directory.
+pub fn character_dir(data_dir: &Path, character: &str) -> PathBuf {
+    data_dir.join("characters").join(sanitize(character))
+}
+
+/// Character and channel names can contain characters that aren't valid in file names.
+pub fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Loads a JSON file, falling back to the default if it doesn't exist yet or can't be read.
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> T {
+    fs::read(path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Writes a JSON file through a temporary file, so a crash never leaves half of it behind.
+pub fn save<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temporary = path.with_extension("tmp");
+    // On disk before the rename, or a crash could leave an empty file in place of the old one
+    let mut file = File::create(&temporary)?;
+    file.write_all(&serde_json::to_vec_pretty(value)?)?;
+    file.sync_all()?;
+    fs::rename(temporary, path)
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use fchat::TypingStatus;
+
+/// How long the composer can sit untouched before we tell the other side we paused.
+const PAUSE_AFTER: Duration = Duration::from_secs(5);
+
+/// Tracks the typing status we last sent, so TPN only goes out when it actually changes.
+pub struct TypingTracker {
+    recipient: Option<String>,
+    sent: TypingStatus,
+    text: String,
+    last_edit: Instant,
+}
+
+impl TypingTracker {
+    pub fn new() -> Self {
+        TypingTracker {
+            recipient: None,
+            sent: TypingStatus::Clear,
+            text: String::new(),
+            last_edit: Instant::now(),
+        }
+    }
+
+    /// Called whenever the composer may have changed. `recipient` is the character of the
+    /// active private conversation, if any.
+    pub fn update(&mut self, recipient: Option<&str>, text: &str) -> Vec<(String, TypingStatus)> {
+        let mut updates = Vec::new();
+        if self.recipient.as_deref() != recipient {
+            if let Some(previous) = self.recipient.take()
+                && self.sent != TypingStatus::Clear
+            {
+                updates.push((previous, TypingStatus::Clear));
+            }
+            self.recipient = recipient.map(str::to_owned);
+            self.sent = TypingStatus::Clear;
+            // Switching tabs with a draft isn't typing, wait for the next edit
+            self.text = text.to_owned();
+            return updates;
+        }
+        let Some(recipient) = &self.recipient else {
+            return updates;
+        };
+        if text != self.text {
+            self.text = text.to_owned();
+            self.last_edit = Instant::now();
+        } else if self.sent == TypingStatus::Paused {
+            return updates;
+        }
+        let status = if text.trim().is_empty() {
+            TypingStatus::Clear
+        } else {
+            TypingStatus::Typing
+        };
+        if status != self.sent {
+            self.sent = status;
+            updates.push((recipient.clone(), status));
+        }
+        updates
+    }
+
+    pub fn tick(&mut self) -> Option<(String, TypingStatus)> {
+        if self.sent != TypingStatus::Typing || self.last_edit.elapsed() < PAUSE_AFTER {
+            return None;
+        }
+        self.sent = TypingStatus::Paused;
+        Some((self.recipient.clone()?, TypingStatus::Paused))
+    }
+}
@@ -1,17 +1,27 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::ops::Range;
+
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
-    style::{Color, Stylize},
-    text::Text,
+    layout::{Position, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span, Text},
     widgets::{
-        Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget,
-        StatefulWidgetRef, Widget, WidgetRef,
+        Block, Borders, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget,
+        StatefulWidgetRef, Widget, WidgetRef, Wrap,
     },
 };
 use ratatui_macros::{horizontal, vertical};
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::bbcode::{self, Link};
+use crate::chat::{
+    ChatLine, Conversation, Conversations, LineKind, RowCounts, ScrollPosition, Viewport,
+};
+use crate::layout::Side;
+
 #[derive(Copy, Clone)]
 pub struct TextArea {
     background: Color,
@@ -21,7 +31,16 @@ pub struct TextArea {
 
 pub struct TextAreaState {
     text: String,
+    /// Byte offset of the cursor.
+    cursor: usize,
+    /// Where a mouse selection started. It runs from here to the cursor.
+    anchor: Option<usize>,
     scrollbar_state: ScrollbarState,
+    /// Byte ranges of the wrapped rows, where the text was and how far it was scrolled when it
+    /// was last drawn, to find what the mouse points at.
+    rows: Vec<Range<usize>>,
+    text_area: Rect,
+    scroll: usize,
 }
 
 impl TextArea {
@@ -38,7 +57,20 @@ impl TextAreaState {
     pub fn new() -> Self {
         TextAreaState {
             text: String::new(),
+            cursor: 0,
+            anchor: None,
             scrollbar_state: ScrollbarState::new(0),
+            rows: Vec::new(),
+            text_area: Rect::default(),
+            scroll: 0,
+        }
+    }
+
+    pub fn with_text(text: String) -> Self {
+        TextAreaState {
+            cursor: text.len(),
+            text,
+            ..TextAreaState::new()
         }
     }
 
@@ -59,10 +91,10 @@ impl TextAreaState {
                         }
                     }
                     KeyCode::Enter => {
-                        self.text.push('\n');
+                        self.insert("\n");
                     }
-                    KeyCode::Left => {}
-                    KeyCode::Right => {}
+                    KeyCode::Left => self.move_left(),
+                    KeyCode::Right => self.move_right(),
                     KeyCode::Up => {}
                     KeyCode::Down => {}
                     KeyCode::Home => {}
@@ -75,45 +107,176 @@ impl TextAreaState {
                     KeyCode::Insert => {}
                     KeyCode::Char(c) => {
                         if modifiers == KeyModifiers::NONE || modifiers == KeyModifiers::SHIFT {
-                            self.text.push(c);
+                            self.insert(c.encode_utf8(&mut [0; 4]));
                         }
                     }
                     _ => {}
                 };
             }
-            crossterm::event::Event::Mouse(_) => {}
+            crossterm::event::Event::Mouse(mouse) => self.mouse(*mouse),
             crossterm::event::Event::Paste(data) => {
-                self.paste(data);
+                self.insert(data);
             }
             crossterm::event::Event::Resize(_, _) => {}
             _ => {}
         }
     }
 
+    /// Clicking moves the cursor, dragging selects.
+    fn mouse(&mut self, mouse: MouseEvent) {
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left)
+                if self
+                    .text_area
+                    .contains(Position::new(mouse.column, mouse.row)) =>
+            {
+                self.cursor = self.offset_at(mouse.column, mouse.row);
+                self.anchor = Some(self.cursor);
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.anchor.is_some() => {
+                self.cursor = self.offset_at(mouse.column, mouse.row);
+            }
+            MouseEventKind::Up(MouseButton::Left) if self.selection_range().is_none() => {
+                self.anchor = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// The byte offset shown at a screen position in the last draw, clamped to the text.
+    fn offset_at(&self, column: u16, row: u16) -> usize {
+        let Some(last) = self.rows.len().checked_sub(1) else {
+            return 0;
+        };
+        let index = (row.saturating_sub(self.text_area.y) as usize + self.scroll).min(last);
+        let range = self.rows[index].clone();
+        let column = column.saturating_sub(self.text_area.x) as usize;
+        let mut width = 0;
+        let mut last_grapheme = range.start;
+        for (offset, grapheme) in self.text[range.clone()].grapheme_indices(true) {
+            width += Span::raw(grapheme).width();
+            if width > column {
+                return range.start + offset;
+            }
+            last_grapheme = range.start + offset;
+        }
+        // Past the end of a wrapped row, the cursor goes on the space it was broken at
+        match self.rows.get(index + 1) {
+            Some(next) if next.start == range.end && range.end > range.start => last_grapheme,
+            _ => range.end,
+        }
+    }
+
+    fn insert(&mut self, data: &str) {
+        self.delete_selection();
+        self.text.insert_str(self.cursor, data);
+        self.cursor += data.len();
+    }
+
+    /// Removes the selected text, returning whether there was any.
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection_range();
+        self.anchor = None;
+        let Some(selection) = selection else {
+            return false;
+        };
+        self.cursor = selection.start;
+        self.text.replace_range(selection, "");
+        true
+    }
+
     fn delete_char(&mut self) {
-        self.text.pop();
+        if self.delete_selection() {
+            return;
+        }
+        let Some((boundary, _)) = self.text[..self.cursor].grapheme_indices(true).next_back()
+        else {
+            return;
+        };
+        self.text.replace_range(boundary..self.cursor, "");
+        self.cursor = boundary;
     }
 
     fn delete_word(&mut self) {
-        let Some((boundary, _)) = self.text.unicode_word_indices().last() else {
+        if self.delete_selection() {
+            return;
+        }
+        let Some((boundary, _)) = self.text[..self.cursor].unicode_word_indices().next_back()
+        else {
             return;
         };
-        self.text.truncate(boundary);
+        self.text.replace_range(boundary..self.cursor, "");
+        self.cursor = boundary;
+    }
+
+    fn move_left(&mut self) {
+        self.anchor = None;
+        if let Some((boundary, _)) = self.text[..self.cursor].grapheme_indices(true).next_back() {
+            self.cursor = boundary;
+        }
+    }
+
+    fn move_right(&mut self) {
+        self.anchor = None;
+        if let Some(grapheme) = self.text[self.cursor..].graphemes(true).next() {
+            self.cursor += grapheme.len();
+        }
+    }
+
+    fn selection_range(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor)).filter(|range| !range.is_empty())
+    }
+
+    /// The text selected with the mouse, if any.
+    pub fn selection(&self) -> Option<&str> {
+        self.selection_range().map(|range| &self.text[range])
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
     }
 
-    fn paste(&mut self, data: &str) {
-        self.text.push_str(data);
+    pub fn take_text(&mut self) -> String {
+        self.cursor = 0;
+        self.anchor = None;
+        std::mem::take(&mut self.text)
     }
+}
 
-    pub fn wrapped_text(&self, width: usize) -> String {
-        let mut text = self.text.clone();
-        // Dummy character representing the cursor, so trailing whitespace doesn't get trimmed
-        text.push('_');
-        let mut wrapped_text = textwrap::fill(&text, width);
-        // Remove dummy character
-        wrapped_text.pop();
-        wrapped_text
+/// Wraps `text` the way it's shown, as byte ranges into it. Whitespace where a row was broken
+/// stays at the end of the row before, so the cursor can sit on it.
+fn wrap_rows(text: &str, width: usize) -> Vec<Range<usize>> {
+    let mut rows: Vec<Range<usize>> = Vec::new();
+    let mut start = 0;
+    for paragraph in text.split('\n') {
+        let end = start + paragraph.len();
+        let first = rows.len();
+        let mut position = start;
+        for line in textwrap::wrap(paragraph, width) {
+            let offset = text[position..end]
+                .find(line.as_ref())
+                .map_or(position, |offset| position + offset);
+            // textwrap trims the whitespace it breaks at, give it back to the row before
+            if let Some(previous) = rows[first..].last_mut() {
+                previous.end = offset;
+            }
+            let row_end = (offset + line.len()).min(end);
+            rows.push(offset..row_end);
+            position = row_end;
+        }
+        match rows[first..].first_mut() {
+            Some(row) => {
+                row.start = start;
+                if let Some(last) = rows.last_mut() {
+                    last.end = end;
+                }
+            }
+            None => rows.push(start..end),
+        }
+        start = end + 1;
     }
+    rows
 }
 
 impl StatefulWidgetRef for TextArea {
@@ -122,26 +285,55 @@ impl StatefulWidgetRef for TextArea {
     fn render_ref(&self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
         let word_count = state.text.unicode_words().count();
         let byte_count = state.text.len();
-        let wrapped_text = state.wrapped_text(area.width as usize);
-        let trailing_newline = wrapped_text.ends_with('\n');
-        let mut text = Text::raw(wrapped_text);
-        state.scrollbar_state = state
-            .scrollbar_state
-            .content_length(text.lines.len())
-            .position(text.lines.len());
-        // Text::raw trims the last trailing newline, so we have to add it back in
-        if trailing_newline {
-            text.push_line("");
-        }
-        if text.lines.last().map(|line| line.width()).unwrap_or(0) < area.width as usize {
-            text.push_span("_".on_yellow());
-        } else {
-            text.push_line("_".on_yellow());
-        }
         let [area, status_area] = vertical![*=1, ==1].areas(area);
         let [text_area, scrollbar_area] = horizontal![*=1, ==1].areas(area);
+        let width = (text_area.width as usize).max(1);
+        let rows = wrap_rows(&state.text, width);
+        let selection = state.selection_range();
+        let cursor_row = rows
+            .partition_point(|row| row.start <= state.cursor)
+            .saturating_sub(1);
+        let mut text = Text::default();
+        for (index, row) in rows.iter().enumerate() {
+            let mut line = Line::default();
+            for (offset, grapheme) in state.text[row.clone()].grapheme_indices(true) {
+                let offset = row.start + offset;
+                let span = Span::raw(grapheme.to_owned());
+                line.push_span(if offset == state.cursor {
+                    span.on_yellow()
+                } else if selection
+                    .as_ref()
+                    .is_some_and(|range| range.contains(&offset))
+                {
+                    span.reversed()
+                } else {
+                    span
+                });
+            }
+            text.push_line(line);
+            // A cursor past the end of a row, goes after it or on a row of its own if it's full
+            if index == cursor_row && state.cursor >= row.end {
+                if text.lines.last().map(|line| line.width()).unwrap_or(0) < width {
+                    text.push_span("_".on_yellow());
+                } else {
+                    text.push_line("_".on_yellow());
+                }
+            }
+        }
+        let cursor_line = if text.lines.len() > rows.len() {
+            text.lines.len() - 1
+        } else {
+            cursor_row
+        };
         let number_of_lines = text.lines.len();
-        let scroll = number_of_lines.saturating_sub(text_area.height as usize);
+        state.scrollbar_state = state
+            .scrollbar_state
+            .content_length(number_of_lines)
+            .position(cursor_line);
+        let scroll = (cursor_line + 1).saturating_sub(text_area.height as usize);
+        state.rows = rows;
+        state.text_area = text_area;
+        state.scroll = scroll;
         Paragraph::new(text)
             .scroll((scroll as u16, 0))
             .bg(self.background)
@@ -164,3 +356,712 @@ impl Default for TextArea {
         Self::new()
     }
 }
+
+pub struct TabBar<'a> {
+    conversations: &'a Conversations,
+    active: Color,
+    mention: Color,
+}
+
+impl<'a> TabBar<'a> {
+    pub fn new(conversations: &'a Conversations) -> Self {
+        TabBar {
+            conversations,
+            active: Color::Indexed(18),
+            mention: Color::LightRed,
+        }
+    }
+
+    fn tab_spans(&self, index: usize, tab: &Conversation) -> Vec<Span<'static>> {
+        let mut spans = Vec::new();
+        let mut label = format!(" {}", tab.title);
+        if tab.unread > 0 {
+            label.push_str(&format!(" ({})", tab.unread));
+        }
+        let mut span = Span::raw(label);
+        if index == self.conversations.active_index() {
+            span = span.bg(self.active).bold();
+        }
+        spans.push(span);
+        if tab.mentions > 0 {
+            spans.push(Span::raw(format!(" @{}", tab.mentions)).fg(self.mention));
+        }
+        spans.push(Span::raw(" |"));
+        spans
+    }
+
+    /// The tab drawn at `column`, counted from the left edge of the bar.
+    pub fn tab_at(&self, column: u16) -> Option<usize> {
+        let mut right = 0;
+        for (index, tab) in self.conversations.tabs().iter().enumerate() {
+            right += self
+                .tab_spans(index, tab)
+                .iter()
+                .map(Span::width)
+                .sum::<usize>();
+            if (column as usize) < right {
+                return Some(index);
+            }
+        }
+        None
+    }
+}
+
+impl Widget for TabBar<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut spans = Vec::new();
+        for (index, tab) in self.conversations.tabs().iter().enumerate() {
+            spans.extend(self.tab_spans(index, tab));
+        }
+        Line::from(spans).render(area, buf);
+    }
+}
+
+/// Columns and rows of the avatar next to every name in the member list.
+const MEMBER_AVATAR_WIDTH: u16 = 4;
+const MEMBER_AVATAR_HEIGHT: u16 = 2;
+
+/// Who's in a channel, with a border towards the scrollback.
+pub struct MemberList<'a> {
+    conversation: &'a Conversation,
+    borders: Borders,
+    /// Whether every name gets room for an avatar in front of it.
+    avatars: bool,
+    op: Color,
+}
+
+impl<'a> MemberList<'a> {
+    pub fn new(conversation: &'a Conversation, side: Side) -> Self {
+        MemberList {
+            conversation,
+            borders: match side {
+                Side::Left => Borders::RIGHT,
+                Side::Right => Borders::LEFT,
+            },
+            avatars: false,
+            op: Color::Yellow,
+        }
+    }
+
+    pub fn avatars(mut self, avatars: bool) -> Self {
+        self.avatars = avatars;
+        self
+    }
+
+    fn block(&self) -> Block<'static> {
+        let title = format!("{} here", self.conversation.members.len());
+        Block::new().borders(self.borders).title(title)
+    }
+
+    /// Where every member on screen is drawn, if the list is drawn in `area`.
+    fn rows(&self, area: Rect) -> Vec<(Rect, &'a str)> {
+        let inner = self.block().inner(area);
+        let height = if self.avatars {
+            MEMBER_AVATAR_HEIGHT
+        } else {
+            1
+        };
+        let fits = (inner.height / height) as usize;
+        self.conversation
+            .members
+            .iter()
+            .map(String::as_str)
+            .skip(self.conversation.members_scroll)
+            .take(fits)
+            .enumerate()
+            .map(|(index, member)| {
+                let y = inner.y + index as u16 * height;
+                (Rect { y, height, ..inner }, member)
+            })
+            .collect()
+    }
+
+    /// The member drawn at `position`, if the list is drawn in `area`.
+    pub fn member_at(&self, area: Rect, position: Position) -> Option<&'a str> {
+        self.rows(area)
+            .into_iter()
+            .find(|(row, _)| row.contains(position))
+            .map(|(_, member)| member)
+    }
+
+    /// Where to draw the avatar of every member on screen.
+    pub fn avatar_areas(&self, area: Rect) -> Vec<(Rect, &'a str)> {
+        if !self.avatars {
+            return Vec::new();
+        }
+        self.rows(area)
+            .into_iter()
+            .map(|(row, member)| {
+                let width = MEMBER_AVATAR_WIDTH.min(row.width);
+                (Rect { width, ..row }, member)
+            })
+            .collect()
+    }
+}
+
+impl Widget for MemberList<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let indent = if self.avatars {
+            MEMBER_AVATAR_WIDTH + 1
+        } else {
+            0
+        };
+        for (row, member) in self.rows(area) {
+            let line = if self.conversation.is_op(member) {
+                Line::from(format!("@{}", member)).fg(self.op)
+            } else {
+                Line::from(format!(" {}", member))
+            };
+            let name_area = Rect {
+                x: row.x + indent.min(row.width),
+                width: row.width.saturating_sub(indent),
+                height: 1,
+                ..row
+            };
+            line.render(name_area, buf);
+        }
+        self.block().render(area, buf);
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Scrollback {
+    mention: Color,
+    history: Color,
+    search_hit: Color,
+    current_hit: Color,
+    roll: Color,
+    /// Whether links are written as OSC 8 hyperlinks.
+    hyperlinks: bool,
+    /// Whether eicons get room for their image, rather than showing as text.
+    eicons: bool,
+}
+
+impl Scrollback {
+    pub fn new() -> Scrollback {
+        Scrollback {
+            mention: Color::Indexed(52),
+            history: Color::DarkGray,
+            search_hit: Color::Yellow,
+            current_hit: Color::Indexed(58),
+            roll: Color::LightMagenta,
+            hyperlinks: false,
+            eicons: false,
+        }
+    }
+
+    pub fn hyperlinks(mut self, hyperlinks: bool) -> Self {
+        self.hyperlinks = hyperlinks;
+        self
+    }
+
+    pub fn eicons(mut self, eicons: bool) -> Self {
+        self.eicons = eicons;
+        self
+    }
+}
+
+/// Splits a row into spans so that every case-insensitive occurrence of `query` stands out.
+fn highlight_row(row: String, style: Style, query: &str, highlight: Style) -> Line<'static> {
+    let lowercase = row.to_lowercase();
+    // Lowercasing can change byte offsets for some scripts, don't risk slicing mid-character
+    if query.is_empty() || lowercase.len() != row.len() {
+        return Line::styled(row, style);
+    }
+    let mut spans = Vec::new();
+    let mut end = 0;
+    for (start, hit) in lowercase.match_indices(query) {
+        spans.push(Span::styled(row[end..start].to_owned(), style));
+        end = start + hit.len();
+        spans.push(Span::styled(
+            row[start..end].to_owned(),
+            style.patch(highlight),
+        ));
+    }
+    spans.push(Span::styled(row[end..].to_owned(), style));
+    Line::from(spans)
+}
+
+/// Something in a row of the scrollback that's more than its text.
+enum Inline {
+    Eicon(String),
+    Link(Link),
+}
+
+/// Tags that become an `Inline`, with their closing tag. Tags ending in `=` take an argument.
+const INLINE_TAGS: [(&str, &str); 6] = [
+    ("[eicon]", "[/eicon]"),
+    ("[url]", "[/url]"),
+    ("[url=", "[/url]"),
+    ("[user]", "[/user]"),
+    ("[icon]", "[/icon]"),
+    ("[session=", "[/session]"),
+];
+
+/// Columns and rows an eicon takes up in the scrollback, about square in most fonts.
+const EICON_WIDTH: u16 = 6;
+const EICON_HEIGHT: u16 = 3;
+
+/// Finds eicons, links and bare web addresses in a wrapped row, with the bytes each takes up.
+/// Tags split over two rows stay text.
+fn inline_tags(row: &str) -> Vec<(Range<usize>, Inline)> {
+    // ASCII lowercasing keeps byte offsets, so positions found in it work in the row too
+    let lowercase = row.to_ascii_lowercase();
+    let mut tags = Vec::new();
+    let mut start = 0;
+    while start < row.len() {
+        let rest = &lowercase[start..];
+        let found = if let Some((open, close)) =
+            INLINE_TAGS.iter().find(|(open, _)| rest.starts_with(open))
+        {
+            inline_tag(&row[start..], rest, open, close)
+        } else if (start == 0 || row[..start].ends_with(char::is_whitespace))
+            && let Some(url) = bbcode::bare_url(&row[start..])
+        {
+            Some((url.len(), Inline::Link(Link::Url(url.to_owned()))))
+        } else {
+            None
+        };
+        match found {
+            Some((length, inline)) => {
+                tags.push((start..start + length, inline));
+                start += length;
+            }
+            None => {
+                start += row[start..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+    }
+    tags
+}
+
+/// Lays out a wrapped row with its inline tags, as the row to show and the column and width of
+/// every tag in it. With `eicons`, their tags are swapped for blank room for the image.
+fn place_inline(row: &str, eicons: bool) -> (String, Vec<(u16, u16, Inline)>) {
+    let mut shown = String::new();
+    let mut placed = Vec::new();
+    let mut end = 0;
+    for (range, inline) in inline_tags(row) {
+        shown.push_str(&row[end..range.start]);
+        let column = Span::raw(shown.as_str()).width() as u16;
+        match inline {
+            Inline::Eicon(name) if eicons => {
+                shown.push_str(&" ".repeat(EICON_WIDTH as usize));
+                placed.push((column, EICON_WIDTH, Inline::Eicon(name)));
+            }
+            inline => {
+                let width = Span::raw(&row[range.clone()]).width() as u16;
+                shown.push_str(&row[range.clone()]);
+                placed.push((column, width, inline));
+            }
+        }
+        end = range.end;
+    }
+    shown.push_str(&row[end..]);
+    (shown, placed)
+}
+
+/// Where the sender's name starts in the first row of a line, right after the timestamp.
+fn sender_column(row: &str, sender: &str) -> Option<u16> {
+    let after_timestamp = row.find("] ")? + 2;
+    let start = row[after_timestamp..].find(sender)? + after_timestamp;
+    Some(Span::raw(&row[..start]).width() as u16)
+}
+
+/// Reads one tag at the start of `row`, returning how long it is.
+fn inline_tag(row: &str, lowercase: &str, open: &str, close: &str) -> Option<(usize, Inline)> {
+    let (argument, text_start) = if open.ends_with('=') {
+        let end = lowercase.find(']')?;
+        (Some(&row[open.len()..end]), end + 1)
+    } else {
+        (None, open.len())
+    };
+    let text_end = lowercase[text_start..].find(close)? + text_start;
+    let text = row[text_start..text_end].to_owned();
+    let inline = match (open, argument) {
+        ("[eicon]", _) => Inline::Eicon(text),
+        ("[user]" | "[icon]", _) => Inline::Link(Link::User(text)),
+        ("[session=", Some(title)) => Inline::Link(Link::Session {
+            title: title.to_owned(),
+            channel: text,
+        }),
+        (_, Some(url)) => Inline::Link(Link::Url(url.to_owned())),
+        (_, None) => Inline::Link(Link::Url(text)),
+    };
+    Some((text_end + close.len(), inline))
+}
+
+/// Turns the cells in `area` into an OSC 8 hyperlink. Cells are wrapped two at a time: the
+/// escape codes make a cell look wider than it is, so the diff skips the cell after it, which
+/// is the one it already contains.
+fn hyperlink(buf: &mut Buffer, area: Rect, url: &str) {
+    let url: String = url.chars().filter(|c| !c.is_control()).collect();
+    for x in (area.left()..area.right()).step_by(2) {
+        let mut text = String::new();
+        for x in x..(x + 2).min(area.right()) {
+            text.push_str(buf[(x, area.y)].symbol());
+        }
+        buf[(x, area.y)].set_symbol(&format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text));
+    }
+}
+
+/// A channel's description under the header, either just its first line or all of it.
+pub struct Description {
+    text: Text<'static>,
+    expanded: bool,
+    hint: Color,
+}
+
+impl Description {
+    pub fn new(conversation: &Conversation) -> Self {
+        Description {
+            text: bbcode::render(&conversation.description),
+            expanded: conversation.description_expanded,
+            hint: Color::DarkGray,
+        }
+    }
+
+    /// Rows needed at this width, at most `max`. Channels without a description need none.
+    pub fn height(&self, width: u16, max: u16) -> u16 {
+        if self.text.lines.iter().all(|line| line.width() == 0) {
+            return 0;
+        }
+        if !self.expanded {
+            return 1;
+        }
+        let width = (width as usize).max(1);
+        let rows: usize = self
+            .text
+            .lines
+            .iter()
+            .map(|line| line.width().div_ceil(width).max(1))
+            .sum();
+        // One more for the border that separates it from the scrollback
+        (rows as u16).saturating_add(1).min(max)
+    }
+}
+
+impl Widget for Description {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if self.expanded {
+            Paragraph::new(self.text)
+                .wrap(Wrap { trim: false })
+                .block(
+                    Block::new()
+                        .borders(Borders::BOTTOM)
+                        .title_bottom(Line::from(" Alt-D: collapse ").fg(self.hint)),
+                )
+                .render(area, buf);
+            return;
+        }
+        let Some(first) = self.text.lines.into_iter().find(|line| line.width() > 0) else {
+            return;
+        };
+        let mut line = first;
+        line.spans
+            .push(Span::raw(" … (Alt-D: expand)").fg(self.hint));
+        line.render(area, buf);
+    }
+}
+
+/// A wrapped row of a chat line, before it's styled.
+struct WrappedRow {
+    text: String,
+    /// Column, width, height and target of every eicon, link and sender name in the row.
+    inline: Vec<(u16, u16, u16, Inline)>,
+    /// Rows taken up, more than one when eicons reach into blank rows under it.
+    height: usize,
+}
+
+impl Scrollback {
+    /// Wraps a line the way it's shown.
+    fn wrap_line(&self, line: &ChatLine, width: usize) -> Vec<WrappedRow> {
+        let mut shown = line.to_string();
+        if line.pending.is_some() {
+            shown.push_str(" (sending…)");
+        }
+        let mut rows = Vec::new();
+        for (index, row) in textwrap::wrap(&shown, width).into_iter().enumerate() {
+            let mut inline = Vec::new();
+            if index == 0
+                && let Some(sender) = &line.sender
+                && let Some(column) = sender_column(&row, sender)
+            {
+                let width = Span::raw(sender.as_str()).width() as u16;
+                inline.push((column, width, 1, Inline::Link(Link::User(sender.clone()))));
+            }
+            let (text, placed) = place_inline(&row, self.eicons);
+            let mut height = 1;
+            for (column, width, tag) in placed {
+                let tag_height = match tag {
+                    Inline::Eicon(_) if self.eicons => EICON_HEIGHT,
+                    _ => 1,
+                };
+                height = height.max(tag_height);
+                inline.push((column, width, tag_height, tag));
+            }
+            rows.push(WrappedRow {
+                text,
+                inline,
+                height: height as usize,
+            });
+        }
+        rows
+    }
+
+    /// Whether the history divider goes right before the line at `index`: the first live line the
+    /// filter shows, so hiding lines never hides the divider too.
+    fn divider_before(state: &Conversation, index: usize) -> bool {
+        state.history > 0
+            && index >= state.history
+            && state.filter.shows(state.lines[index].kind)
+            && !state.lines[state.history..index]
+                .iter()
+                .any(|line| state.filter.shows(line.kind))
+    }
+
+    /// Rows the line at `index` takes up, with the history divider before it if there is one.
+    fn line_height(&self, state: &Conversation, index: usize, width: usize) -> usize {
+        let line = &state.lines[index];
+        // Filtered lines take up no rows, scrolling to one lands on the next shown line
+        if !state.filter.shows(line.kind) {
+            return 0;
+        }
+        let rows: usize = self
+            .wrap_line(line, width)
+            .iter()
+            .map(|row| row.height)
+            .sum();
+        rows + usize::from(Self::divider_before(state, index))
+    }
+
+    fn line_style(&self, state: &Conversation, index: usize) -> Style {
+        let line = &state.lines[index];
+        let current_hit = state
+            .search
+            .as_ref()
+            .is_some_and(|search| search.current == Some(index));
+        if current_hit {
+            Style::new().bg(self.current_hit)
+        } else if line.mention {
+            Style::new().bg(self.mention)
+        } else if index < state.history {
+            Style::new().fg(self.history)
+        } else if line.pending.is_some() {
+            Style::new().fg(self.history).italic()
+        } else if line.kind == LineKind::Roll {
+            Style::new().fg(self.roll)
+        } else {
+            Style::new()
+        }
+    }
+}
+
+impl StatefulWidgetRef for Scrollback {
+    type State = Conversation;
+
+    fn render_ref(&self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let width = (area.width as usize).max(1);
+        let height = area.height as usize;
+        // Only lines that are new since the last draw get wrapped to count their rows
+        let row_counts = &state.row_counts;
+        if row_counts.width != width
+            || row_counts.eicons != self.eicons
+            || row_counts.filter != state.filter
+            || row_counts.counts.len() > state.lines.len()
+        {
+            state.row_counts = RowCounts {
+                width,
+                eicons: self.eicons,
+                filter: state.filter,
+                counts: Vec::with_capacity(state.lines.len()),
+            };
+        }
+        for index in state.row_counts.counts.len()..state.lines.len() {
+            let rows = self.line_height(state, index, width);
+            state.row_counts.counts.push(rows);
+        }
+        // The first row of every line, for scrolling to a specific line
+        let mut first_rows = Vec::with_capacity(state.lines.len());
+        let mut text_rows = 0;
+        for count in &state.row_counts.counts {
+            first_rows.push(text_rows);
+            text_rows += count;
+        }
+        let bottom = text_rows.saturating_sub(height);
+        let scroll = match state.scroll {
+            ScrollPosition::Bottom => bottom,
+            ScrollPosition::Line(index) => first_rows
+                .get(index)
+                .map_or(bottom, |row| (*row).min(bottom)),
+        };
+        let query = state
+            .search
+            .as_ref()
+            .map(|search| search.query.to_lowercase())
+            .unwrap_or_default();
+        // Only the lines on screen are laid out and styled
+        let first_line = first_rows
+            .partition_point(|row| *row <= scroll)
+            .saturating_sub(1);
+        let mut text = Text::default();
+        let mut row = first_rows.get(first_line).copied().unwrap_or_default();
+        // Row, column, width, height and target of every eicon, link and sender on screen
+        let mut inline = Vec::new();
+        let push_row = |text: &mut Text<'static>, row: &mut usize, line: Line<'static>| {
+            if *row >= scroll {
+                text.push_line(line);
+            }
+            *row += 1;
+        };
+        for (index, line) in state.lines.iter().enumerate().skip(first_line) {
+            if row >= scroll + height {
+                break;
+            }
+            if !state.filter.shows(line.kind) {
+                continue;
+            }
+            // Separates restored history from live traffic
+            if Self::divider_before(state, index) {
+                let divider = format!("{:─^width$}", " history ", width = width);
+                push_row(
+                    &mut text,
+                    &mut row,
+                    Line::styled(divider, Style::new().fg(self.history)),
+                );
+            }
+            let style = self.line_style(state, index);
+            // Search hits stand out, otherwise the roller of a roll does
+            let (highlight, highlight_style) = match &line.sender {
+                Some(sender) if query.is_empty() && line.kind == LineKind::Roll => {
+                    (sender.to_lowercase(), Style::new().bold())
+                }
+                _ => (
+                    query.clone(),
+                    Style::new().fg(Color::Black).bg(self.search_hit),
+                ),
+            };
+            for wrapped in self.wrap_line(line, width) {
+                for (column, width, height, tag) in wrapped.inline {
+                    inline.push((row, column, width, height, tag));
+                }
+                let shown = highlight_row(wrapped.text, style, &highlight, highlight_style);
+                push_row(&mut text, &mut row, shown);
+                for _ in 1..wrapped.height {
+                    push_row(&mut text, &mut row, Line::default());
+                }
+            }
+        }
+        let mut eicons = Vec::new();
+        let mut links = Vec::new();
+        let mut names = Vec::new();
+        for (row, column, width, height, tag) in inline {
+            if !(scroll..scroll + area.height as usize).contains(&row) || column >= area.width {
+                continue;
+            }
+            let y = area.y + (row - scroll) as u16;
+            let rect = Rect {
+                x: area.x + column,
+                y,
+                width: width.min(area.width - column),
+                height: height.min(area.bottom() - y),
+            };
+            match tag {
+                Inline::Eicon(name) => eicons.push((rect, name)),
+                Inline::Link(link) => {
+                    if let Link::User(character) = &link {
+                        names.push((rect, character.clone()));
+                    }
+                    links.push((rect, link));
+                }
+            }
+        }
+        Paragraph::new(text).render(area, buf);
+        if self.hyperlinks {
+            for (rect, link) in &links {
+                if let Some(url) = link.url().filter(|url| bbcode::is_web_url(url)) {
+                    hyperlink(buf, *rect, &url);
+                }
+            }
+        }
+        state.viewport = Viewport {
+            rows: text_rows,
+            height: area.height as usize,
+            top: scroll,
+            first_rows,
+            eicons,
+            names,
+        };
+        if state.unseen > 0 && state.scroll != ScrollPosition::Bottom && area.height > 0 {
+            let marker = format!(" {} new messages below (End to jump) ", state.unseen);
+            let marker_area = Rect {
+                y: area.bottom() - 1,
+                height: 1,
+                ..area
+            };
+            Line::from(marker)
+                .centered()
+                .fg(Color::Black)
+                .bg(self.search_hit)
+                .render(marker_area, buf);
+        }
+    }
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use textwrap::core::display_width;
+
+    use super::*;
+
+    /// The rows have to cover the text end to end, or the cursor gets lost between them.
+    fn assert_covers(text: &str, rows: &[Range<usize>]) {
+        assert_eq!(rows.first().map(|row| row.start), Some(0));
+        assert_eq!(rows.last().map(|row| row.end), Some(text.len()));
+        for pair in rows.windows(2) {
+            let gap = &text[pair[0].end..pair[1].start];
+            assert!(gap.is_empty() || gap == "\n", "gap {:?} in {:?}", gap, rows);
+        }
+    }
+
+    #[test]
+    fn wraps_wide_characters_by_display_width() {
+        let text = "日本語のテキスト";
+        let rows = wrap_rows(text, 4);
+        assert_covers(text, &rows);
+        assert_eq!(rows.len(), 4);
+        for row in &rows {
+            assert_eq!(display_width(&text[row.clone()]), 4);
+        }
+    }
+
+    #[test]
+    fn keeps_combining_characters_with_their_base() {
+        let text = "e\u{301}e\u{301}e\u{301} cafe\u{301}s";
+        let rows = wrap_rows(text, 3);
+        assert_covers(text, &rows);
+        for row in &rows {
+            let row = &text[row.clone()];
+            assert!(display_width(row.trim_end()) <= 3, "{:?} is too wide", row);
+            assert!(
+                !row.starts_with('\u{301}'),
+                "{:?} starts with an accent",
+                row
+            );
+        }
+        assert_eq!(&text[rows[0].clone()], "e\u{301}e\u{301}e\u{301} ");
+    }
+
+    #[test]
+    fn keeps_empty_paragraphs_as_rows() {
+        let text = "ab\n\ncd";
+        let rows = wrap_rows(text, 10);
+        assert_eq!(rows, vec![0..2, 3..3, 4..6]);
+    }
+}